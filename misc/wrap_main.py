@@ -0,0 +1,18 @@
+import glob
+from pathlib import Path
+dirs = glob.glob("./tests/testcases/*")
+for dir in dirs:
+    name = Path(dir).joinpath('in')
+    file = open(name, 'r')
+    content = file.read()
+    file.close()
+
+    if content.startswith("main()"):
+        continue
+    if '\n' in content:
+        content = "main() {\n" + content + "\n}"
+    else:
+        content = "main() { " + content + " }"
+    file = open(name, 'w')
+    file.write(content)
+    file.close()
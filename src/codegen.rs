@@ -1,104 +1,558 @@
 use crate::errors::{CodegenError, CompileError, CompileErrorType};
-use crate::parser::{Node, NodeKind, Parser};
-use crate::tokenizer::RawStream;
+use crate::parser::{self, Function, GlobalVar, Node, NodeKind, Program, StrLiteral};
+use crate::preprocessor::Preprocessor;
+use crate::types::{Operation, Type};
+
+// registers for passing arguments (System V AMD64 ABI)
+const ARG_REGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const ARG_REGS32: [&str; 6] = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
+const ARG_REGS8: [&str; 6] = ["dil", "sil", "dl", "cl", "r8b", "r9b"];
 
 #[derive(Debug)]
-pub struct Codegen;
+pub struct Codegen {
+    label_count: usize,      // unique label number in a compilation
+    loop_labels: Vec<usize>, // labels of enclosing loops, innermost last
+}
 
 impl Codegen {
-    fn gen(assembly: &mut Vec<String>, nodes: Vec<Node>) -> Result<(), CompileError> {
-        for node in nodes {
-            Self::gen_code(assembly, node)?;
-            assembly.push("\tpop rax".to_string());
+    fn new() -> Self {
+        Codegen {
+            label_count: 0,
+            loop_labels: vec![],
+        }
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.label_count += 1;
+        self.label_count
+    }
+
+    fn gen(&mut self, assembly: &mut Vec<String>, program: Program) -> Result<(), CompileError> {
+        Self::gen_data(assembly, &program.globals);
+        Self::gen_rodata(assembly, &program.strings);
+        assembly.push(".text".to_string());
+        self.gen_text(assembly, program.functions)
+    }
+
+    // initialized globals go to .data, and the others to .bss
+    fn gen_data(assembly: &mut Vec<String>, globals: &[GlobalVar]) {
+        for (section, initialized) in [(".data", true), (".bss", false)] {
+            let vars: Vec<&GlobalVar> = globals
+                .iter()
+                .filter(|var| var.init.is_some() == initialized)
+                .collect();
+            if vars.is_empty() {
+                continue;
+            }
+            assembly.push(section.to_string());
+            for var in vars {
+                assembly.push(format!(".global {}", var.name));
+                assembly.push(format!(".align {}", var.ty.align()));
+                assembly.push(format!("{}:", var.name));
+                match (var.init, var.ty.size()) {
+                    (Some(init), 1) => assembly.push(format!("\t.byte {}", init)),
+                    (Some(init), 4) => assembly.push(format!("\t.long {}", init)),
+                    (Some(init), _) => assembly.push(format!("\t.quad {}", init)),
+                    (None, size) => assembly.push(format!("\t.zero {}", size)),
+                }
+            }
+        }
+    }
+
+    // string literals are local symbols in .rodata
+    fn gen_rodata(assembly: &mut Vec<String>, strings: &[StrLiteral]) {
+        if strings.is_empty() {
+            return;
+        }
+        assembly.push(".section .rodata".to_string());
+        for string in strings {
+            assembly.push(format!("{}:", string.name));
+            let bytes: Vec<String> = string.data.iter().map(|b| b.to_string()).collect();
+            assembly.push(format!("\t.byte {}", bytes.join(", ")));
+        }
+    }
+
+    fn gen_text(
+        &mut self,
+        assembly: &mut Vec<String>,
+        functions: Vec<Function>,
+    ) -> Result<(), CompileError> {
+        for function in functions {
+            assembly.push(format!(".global {}", function.name));
+            assembly.push(format!("{}:", function.name));
+            // prologue
+            assembly.push("\tpush rbp".to_string());
+            assembly.push("\tmov rbp, rsp".to_string());
+            assembly.push(format!("\tsub rsp, {}", function.stack_size));
+            // store arguments to local variables
+            for (i, param) in function.params.iter().enumerate() {
+                let reg = match param.ty.size() {
+                    1 => ARG_REGS8[i],
+                    4 => ARG_REGS32[i],
+                    _ => ARG_REGS[i],
+                };
+                assembly.push(format!("\tmov [rbp-{}], {}", param.offset, reg));
+            }
+            for node in function.body {
+                self.gen_stmt(assembly, node)?;
+            }
+            // epilogue
+            assembly.push("\tmov rsp, rbp".to_string());
+            assembly.push("\tpop rbp".to_string());
+            assembly.push("\tret".to_string());
         }
         Ok(())
     }
-    fn gen_code(assembly: &mut Vec<String>, node: Node) -> Result<(), CompileError> {
+
+    // statements leave the stack as it was
+    fn gen_stmt(&mut self, assembly: &mut Vec<String>, node: Node) -> Result<(), CompileError> {
         match node.kind {
             NodeKind::Return => {
                 if let Some(lhs) = node.lhs {
-                    Self::gen_code(assembly, *lhs)?;
+                    self.gen_code(assembly, *lhs)?;
                 }
                 assembly.push("\tpop rax".to_string());
                 assembly.push("\tmov rsp, rbp".to_string());
                 assembly.push("\tpop rbp".to_string());
                 assembly.push("\tret".to_string());
-                return Ok(());
             }
-            NodeKind::Number(num) => {
-                let opcode = format!("\tpush {}", num);
-                // println!("dbg! {}", &opcode);
-                assembly.push(opcode);
-                return Ok(());
+            NodeKind::If => {
+                let label = self.new_label();
+                if let Some(cond) = node.cond {
+                    self.gen_code(assembly, *cond)?;
+                }
+                assembly.push("\tpop rax".to_string());
+                assembly.push("\tcmp rax, 0".to_string());
+                assembly.push(format!("\tje .Lelse{}", label));
+                if let Some(then) = node.then {
+                    self.gen_stmt(assembly, *then)?;
+                }
+                assembly.push(format!("\tjmp .Lend{}", label));
+                assembly.push(format!(".Lelse{}:", label));
+                if let Some(els) = node.els {
+                    self.gen_stmt(assembly, *els)?;
+                }
+                assembly.push(format!(".Lend{}:", label));
+            }
+            NodeKind::While => {
+                let label = self.new_label();
+                assembly.push(format!(".Lcontinue{}:", label));
+                if let Some(cond) = node.cond {
+                    self.gen_code(assembly, *cond)?;
+                }
+                assembly.push("\tpop rax".to_string());
+                assembly.push("\tcmp rax, 0".to_string());
+                assembly.push(format!("\tje .Lbreak{}", label));
+                self.loop_labels.push(label);
+                if let Some(then) = node.then {
+                    self.gen_stmt(assembly, *then)?;
+                }
+                self.loop_labels.pop();
+                assembly.push(format!("\tjmp .Lcontinue{}", label));
+                assembly.push(format!(".Lbreak{}:", label));
+            }
+            NodeKind::For => {
+                let label = self.new_label();
+                if let Some(init) = node.init {
+                    self.gen_code(assembly, *init)?;
+                    assembly.push("\tpop rax".to_string());
+                }
+                assembly.push(format!(".Lbegin{}:", label));
+                if let Some(cond) = node.cond {
+                    self.gen_code(assembly, *cond)?;
+                    assembly.push("\tpop rax".to_string());
+                    assembly.push("\tcmp rax, 0".to_string());
+                    assembly.push(format!("\tje .Lbreak{}", label));
+                }
+                self.loop_labels.push(label);
+                if let Some(then) = node.then {
+                    self.gen_stmt(assembly, *then)?;
+                }
+                self.loop_labels.pop();
+                assembly.push(format!(".Lcontinue{}:", label));
+                if let Some(inc) = node.inc {
+                    self.gen_code(assembly, *inc)?;
+                    assembly.push("\tpop rax".to_string());
+                }
+                assembly.push(format!("\tjmp .Lbegin{}", label));
+                assembly.push(format!(".Lbreak{}:", label));
             }
-            NodeKind::Var(val) => {
+            NodeKind::Block => {
+                for stmt in node.body {
+                    self.gen_stmt(assembly, stmt)?;
+                }
+            }
+            NodeKind::Break => {
+                // the parser rejects "break" outside of loops
+                let label = self.loop_labels.last().expect("break outside of loop");
+                assembly.push(format!("\tjmp .Lbreak{}", label));
+            }
+            NodeKind::Continue => {
+                let label = self.loop_labels.last().expect("continue outside of loop");
+                assembly.push(format!("\tjmp .Lcontinue{}", label));
+            }
+            _ => {
+                self.gen_code(assembly, node)?;
+                assembly.push("\tpop rax".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // push the address of a left value
+    fn gen_addr(&mut self, assembly: &mut Vec<String>, node: Node) -> Result<(), CompileError> {
+        match node.kind {
+            NodeKind::Var(offset) => {
                 assembly.push("\tmov rax, rbp".to_string());
-                assembly.push(format!("\tsub rax, {}", val));
+                assembly.push(format!("\tsub rax, {}", offset));
                 assembly.push("\tpush rax".to_string());
+                Ok(())
+            }
+            NodeKind::GlobalVar => {
+                let name = node.name.expect("global variable without name");
+                assembly.push(format!("\tlea rax, {}[rip]", name));
+                assembly.push("\tpush rax".to_string());
+                Ok(())
+            }
+            NodeKind::Deref => {
+                if let Some(lhs) = node.lhs {
+                    self.gen_code(assembly, *lhs)?;
+                }
+                Ok(())
+            }
+            _ => Err(CompileError {
+                error_type: CompileErrorType::Codegen(CodegenError::LValueNotVar),
+                pos: Some(node.span),
+            }),
+        }
+    }
+
+    // replace the address on the stack top with the value it points to
+    fn load(assembly: &mut Vec<String>, ty: &Type) {
+        match ty {
+            // an array is not loaded, its address is used as a pointer
+            Type::Array(..) => return,
+            Type::Char => {
+                assembly.push("\tpop rax".to_string());
+                assembly.push("\tmovsx rax, byte ptr [rax]".to_string());
+            }
+            Type::Int => {
+                assembly.push("\tpop rax".to_string());
+                assembly.push("\tmovsxd rax, dword ptr [rax]".to_string());
+            }
+            // writing to eax clears the upper half of rax
+            Type::UInt => {
+                assembly.push("\tpop rax".to_string());
+                assembly.push("\tmov eax, dword ptr [rax]".to_string());
+            }
+            Type::Long | Type::ULong | Type::Ptr(_) => {
                 assembly.push("\tpop rax".to_string());
                 assembly.push("\tmov rax, [rax]".to_string());
+            }
+        }
+        assembly.push("\tpush rax".to_string());
+    }
+
+    // store the value on the stack top to the address below it,
+    // and replace both with the value converted to the type
+    fn store(assembly: &mut Vec<String>, ty: &Type) {
+        assembly.push("\tpop rdi".to_string());
+        assembly.push("\tpop rax".to_string());
+        Self::write(assembly, ty);
+        assembly.push("\tmov rax, rdi".to_string());
+        Self::truncate(assembly, Some(ty));
+        assembly.push("\tpush rax".to_string());
+    }
+
+    // write rdi to the address in rax
+    fn write(assembly: &mut Vec<String>, ty: &Type) {
+        match ty.size() {
+            1 => assembly.push("\tmov [rax], dil".to_string()),
+            4 => assembly.push("\tmov [rax], edi".to_string()),
+            _ => assembly.push("\tmov [rax], rdi".to_string()),
+        }
+    }
+
+    fn node_type(node: &Node) -> Type {
+        node.ty.clone().expect("expression is not typed")
+    }
+
+    fn gen_code(&mut self, assembly: &mut Vec<String>, mut node: Node) -> Result<(), CompileError> {
+        match node.kind {
+            NodeKind::Number(num) => {
+                // "push" takes only a 32-bit immediate
+                if i32::try_from(num).is_err() {
+                    assembly.push(format!("\tmov rax, {}", num));
+                    assembly.push("\tpush rax".to_string());
+                    return Ok(());
+                }
+                let opcode = format!("\tpush {}", num);
+                assembly.push(opcode);
+                return Ok(());
+            }
+            NodeKind::Var(_) | NodeKind::GlobalVar => {
+                let ty = Self::node_type(&node);
+                self.gen_addr(assembly, node)?;
+                Self::load(assembly, &ty);
+                return Ok(());
+            }
+            NodeKind::Addr => {
+                if let Some(lhs) = node.lhs {
+                    self.gen_addr(assembly, *lhs)?;
+                }
+                return Ok(());
+            }
+            NodeKind::Deref => {
+                let ty = Self::node_type(&node);
+                if let Some(lhs) = node.lhs {
+                    self.gen_code(assembly, *lhs)?;
+                }
+                Self::load(assembly, &ty);
+                return Ok(());
+            }
+            NodeKind::FuncCall => {
+                let nargs = node.args.len();
+                for arg in node.args {
+                    self.gen_code(assembly, arg)?;
+                }
+                for reg in ARG_REGS[..nargs].iter().rev() {
+                    assembly.push(format!("\tpop {}", reg));
+                }
+                // rsp must be aligned to 16 bytes at "call"
+                let label = self.new_label();
+                let funcname = node.name.expect("function call without name");
+                assembly.push("\tmov rax, rsp".to_string());
+                assembly.push("\tand rax, 15".to_string());
+                assembly.push(format!("\tjnz .Lcall{}", label));
+                assembly.push("\tmov rax, 0".to_string());
+                assembly.push(format!("\tcall {}", funcname));
+                assembly.push(format!("\tjmp .Lend{}", label));
+                assembly.push(format!(".Lcall{}:", label));
+                assembly.push("\tsub rsp, 8".to_string());
+                assembly.push("\tmov rax, 0".to_string());
+                assembly.push(format!("\tcall {}", funcname));
+                assembly.push("\tadd rsp, 8".to_string());
+                assembly.push(format!(".Lend{}:", label));
+                // the upper bits of a narrow return value are not defined by the ABI
+                Self::truncate(assembly, node.ty.as_ref());
                 assembly.push("\tpush rax".to_string());
                 return Ok(());
             }
             NodeKind::Assign => {
+                let ty = Self::node_type(&node);
                 if let Some(lhs) = node.lhs {
-                    let node = *lhs;
-                    if let NodeKind::Var(val) = node.kind {
-                        assembly.push("\tmov rax, rbp".to_string());
-                        assembly.push(format!("\tsub rax, {}", val));
-                        assembly.push("\tpush rax".to_string());
-                    } else {
-                        return Err(CompileError {
-                            error_type: CompileErrorType::Codegen(CodegenError::LValueNotVar),
-                            pos: None,
-                        });
-                    }
+                    self.gen_addr(assembly, *lhs)?;
                 } else {
                     return Err(CompileError {
                         error_type: CompileErrorType::Codegen(CodegenError::LValueNotVar),
-                        pos: None,
+                        pos: Some(node.span),
                     });
                 }
                 if let Some(rhs) = node.rhs {
-                    Self::gen_code(assembly, *rhs)?;
+                    self.gen_code(assembly, *rhs)?;
                 } else {
                     return Err(CompileError {
                         error_type: CompileErrorType::Codegen(CodegenError::RValueNotFound),
-                        pos: None,
+                        pos: Some(node.span),
                     });
                 }
                 // 先にスタックに積んだ値がlvalueなのでraxにpopする
                 // 次にスタックに積まれた値はrvalueなのでrdiにpopする
-                assembly.push("\tpop rdi".to_string());
+                Self::store(assembly, &ty);
+                return Ok(());
+            }
+            // the right operand is evaluated only if it decides the result
+            NodeKind::LogAnd | NodeKind::LogOr => {
+                let label = self.new_label();
+                let (jump, short) = if node.kind == NodeKind::LogAnd {
+                    ("je", 0)
+                } else {
+                    ("jne", 1)
+                };
+                for operand in [node.lhs, node.rhs].into_iter().flatten() {
+                    self.gen_code(assembly, *operand)?;
+                    assembly.push("\tpop rax".to_string());
+                    assembly.push("\tcmp rax, 0".to_string());
+                    assembly.push(format!("\t{} .Lshort{}", jump, label));
+                }
+                assembly.push(format!("\tpush {}", 1 - short));
+                assembly.push(format!("\tjmp .Lend{}", label));
+                assembly.push(format!(".Lshort{}:", label));
+                assembly.push(format!("\tpush {}", short));
+                assembly.push(format!(".Lend{}:", label));
+                return Ok(());
+            }
+            NodeKind::Not => {
+                if let Some(lhs) = node.lhs {
+                    self.gen_code(assembly, *lhs)?;
+                }
+                assembly.push("\tpop rax".to_string());
+                assembly.push("\tcmp rax, 0".to_string());
+                assembly.push("\tsete al".to_string());
+                assembly.push("\tmovzb rax, al".to_string());
+                assembly.push("\tpush rax".to_string());
+                return Ok(());
+            }
+            // the address of the target is computed once, and kept on the stack
+            NodeKind::AssignOp | NodeKind::PostAssignOp => {
+                let ty = Self::node_type(&node);
+                let post = node.kind == NodeKind::PostAssignOp;
+                let mut operation = *node.lhs.expect("assignment without operation");
+                let target = operation.lhs.take().expect("assignment without target");
+                let value = operation.rhs.take().expect("assignment without value");
+                let value_ty = value.ty.clone();
+                self.gen_addr(assembly, *target)?;
+                assembly.push("\tpush qword ptr [rsp]".to_string());
+                Self::load(assembly, &ty);
+                if post {
+                    // keep the old value as the result
+                    assembly.push("\tpush qword ptr [rsp]".to_string());
+                }
+                self.gen_code(assembly, *value)?;
+                Self::gen_binary(assembly, &operation, Some(&ty), value_ty.as_ref())?;
+                if post {
+                    assembly.push("\tpop rdi".to_string());
+                    assembly.push("\tpop rdx".to_string());
+                    assembly.push("\tpop rax".to_string());
+                    Self::write(assembly, &ty);
+                    assembly.push("\tpush rdx".to_string());
+                } else {
+                    Self::store(assembly, &ty);
+                }
+                return Ok(());
+            }
+            NodeKind::Cond => {
+                let label = self.new_label();
+                if let Some(cond) = node.cond {
+                    self.gen_code(assembly, *cond)?;
+                }
+                assembly.push("\tpop rax".to_string());
+                assembly.push("\tcmp rax, 0".to_string());
+                assembly.push(format!("\tje .Lelse{}", label));
+                if let Some(then) = node.then {
+                    self.gen_code(assembly, *then)?;
+                }
+                assembly.push(format!("\tjmp .Lend{}", label));
+                assembly.push(format!(".Lelse{}:", label));
+                if let Some(els) = node.els {
+                    self.gen_code(assembly, *els)?;
+                }
+                assembly.push(format!(".Lend{}:", label));
+                return Ok(());
+            }
+            // the value of the left operand is discarded
+            NodeKind::Comma => {
+                if let Some(lhs) = node.lhs {
+                    self.gen_code(assembly, *lhs)?;
+                }
+                assembly.push("\tpop rax".to_string());
+                if let Some(rhs) = node.rhs {
+                    self.gen_code(assembly, *rhs)?;
+                }
+                return Ok(());
+            }
+            NodeKind::BitNot => {
+                if let Some(lhs) = node.lhs {
+                    self.gen_code(assembly, *lhs)?;
+                }
                 assembly.push("\tpop rax".to_string());
-                assembly.push("\tmov [rax], rdi".to_string());
-                assembly.push("\tpush rdi".to_string());
+                assembly.push("\tnot rax".to_string());
+                Self::truncate(assembly, node.ty.as_ref());
+                assembly.push("\tpush rax".to_string());
                 return Ok(());
             }
             _ => {}
         }
-        if let Some(lhs) = node.lhs {
-            Self::gen_code(assembly, *lhs)?;
+        let lhs_ty = node.lhs.as_ref().and_then(|lhs| lhs.ty.clone());
+        let rhs_ty = node.rhs.as_ref().and_then(|rhs| rhs.ty.clone());
+        if let Some(lhs) = node.lhs.take() {
+            self.gen_code(assembly, *lhs)?;
         }
-        if let Some(rhs) = node.rhs {
-            Self::gen_code(assembly, *rhs)?;
+        if let Some(rhs) = node.rhs.take() {
+            self.gen_code(assembly, *rhs)?;
         }
+        Self::gen_binary(assembly, &node, lhs_ty.as_ref(), rhs_ty.as_ref())
+    }
+
+    // replace the two operands on the stack top with the result of the operator `node.kind`
+    fn gen_binary(
+        assembly: &mut Vec<String>,
+        node: &Node,
+        lhs_ty: Option<&Type>,
+        rhs_ty: Option<&Type>,
+    ) -> Result<(), CompileError> {
+        let operation = Operation::new(node, lhs_ty, rhs_ty);
         assembly.push("\tpop rdi".to_string());
         assembly.push("\tpop rax".to_string());
+        if operation.operand_ty == Some(Type::UInt) {
+            assembly.push("\tmov eax, eax".to_string());
+            assembly.push("\tmov edi, edi".to_string());
+        }
         match node.kind {
             NodeKind::Add => {
+                match (operation.lhs_scale, operation.rhs_scale) {
+                    (Some(size), _) => assembly.push(format!("\timul rdi, {}", size)),
+                    (_, Some(size)) => assembly.push(format!("\timul rax, {}", size)),
+                    _ => {}
+                }
                 assembly.push("\tadd rax, rdi".to_string());
             }
-            NodeKind::Sub => {
-                assembly.push("\tsub rax, rdi".to_string());
-            }
+            NodeKind::Sub => match (operation.lhs_scale, operation.rhs_scale) {
+                (Some(size), Some(_)) => {
+                    // pointer - pointer is the number of elements between them
+                    assembly.push("\tsub rax, rdi".to_string());
+                    assembly.push(format!("\tmov rdi, {}", size));
+                    assembly.push("\tcqo".to_string());
+                    assembly.push("\tidiv rdi".to_string());
+                }
+                (Some(size), None) => {
+                    assembly.push(format!("\timul rdi, {}", size));
+                    assembly.push("\tsub rax, rdi".to_string());
+                }
+                _ => {
+                    assembly.push("\tsub rax, rdi".to_string());
+                }
+            },
             NodeKind::Mul => {
                 assembly.push("\timul rax, rdi".to_string());
             }
+            NodeKind::Div if operation.unsigned => {
+                assembly.push("\tmov rdx, 0".to_string());
+                assembly.push("\tdiv rdi".to_string());
+            }
             NodeKind::Div => {
                 assembly.push("\tcqo".to_string());
                 assembly.push("\tidiv rdi".to_string());
             }
+            // the remainder is left in rdx
+            NodeKind::Mod if operation.unsigned => {
+                assembly.push("\tmov rdx, 0".to_string());
+                assembly.push("\tdiv rdi".to_string());
+                assembly.push("\tmov rax, rdx".to_string());
+            }
+            NodeKind::Mod => {
+                assembly.push("\tcqo".to_string());
+                assembly.push("\tidiv rdi".to_string());
+                assembly.push("\tmov rax, rdx".to_string());
+            }
+            NodeKind::BitAnd => {
+                assembly.push("\tand rax, rdi".to_string());
+            }
+            NodeKind::BitOr => {
+                assembly.push("\tor rax, rdi".to_string());
+            }
+            NodeKind::BitXor => {
+                assembly.push("\txor rax, rdi".to_string());
+            }
+            NodeKind::Shl => {
+                assembly.push("\tmov rcx, rdi".to_string());
+                assembly.push("\tshl rax, cl".to_string());
+            }
+            NodeKind::Shr => {
+                let shift = if operation.unsigned { "shr" } else { "sar" };
+                assembly.push("\tmov rcx, rdi".to_string());
+                assembly.push(format!("\t{} rax, cl", shift));
+            }
             NodeKind::Eq => {
                 assembly.push("\tcmp rax, rdi".to_string());
                 assembly.push("\tsete al".to_string());
@@ -111,260 +565,165 @@ impl Codegen {
             }
             NodeKind::Less => {
                 assembly.push("\tcmp rax, rdi".to_string());
-                assembly.push("\tsetl al".to_string());
+                let set = if operation.unsigned { "setb" } else { "setl" };
+                assembly.push(format!("\t{} al", set));
                 assembly.push("\tmovzb rax, al".to_string());
             }
             NodeKind::LessEq => {
                 assembly.push("\tcmp rax, rdi".to_string());
-                assembly.push("\tsetle al".to_string());
+                let set = if operation.unsigned { "setbe" } else { "setle" };
+                assembly.push(format!("\t{} al", set));
                 assembly.push("\tmovzb rax, al".to_string());
             }
-            _ => unreachable!(), // TODO parse error message
+            // statements are not generated by gen_code
+            _ => {
+                return Err(CompileError {
+                    error_type: CompileErrorType::Codegen(CodegenError::NotExpression),
+                    pos: Some(node.span.clone()),
+                })
+            }
         }
+        Self::truncate(assembly, operation.result_ty.as_ref());
         assembly.push("\tpush rax".to_string());
         Ok(())
     }
 
+    // results narrower than 64 bits wrap around
+    fn truncate(assembly: &mut Vec<String>, ty: Option<&Type>) {
+        match ty {
+            Some(Type::Char) => assembly.push("\tmovsx rax, al".to_string()),
+            Some(Type::Int) => assembly.push("\tmovsxd rax, eax".to_string()),
+            Some(Type::UInt) => assembly.push("\tmov eax, eax".to_string()),
+            _ => {}
+        }
+    }
+
     pub fn compile(input: &str) -> Result<Vec<String>, Vec<CompileError>> {
-        let mut tokens = RawStream::new(input);
-        let mut assembly: Vec<String> = vec![
-            ".intel_syntax noprefix",
-            ".global main",
-            "main:",
-            "\tpush rbp",
-            "\tmov rbp, rsp",
-            "\tsub rsp, 208",
-        ]
-        .iter()
-        .map(|e| e.to_string())
-        .collect();
-        // remove tokenize error and return tokens
-        let tokens = tokens.check()?;
-        let mut tokens = tokens.into_iter().peekable();
-        // let mut tokens = tokens.iter().peekable();
-        // println!("{:?}", tokens);
-        let mut parser = Parser::new();
-        let node = parser.program(&mut tokens).map_err(|e| vec![e])?;
-        // println!("dbg! {:#?}", node);
-        Self::gen(&mut assembly, node).map_err(|e| vec![e])?;
-        assembly.push("\tmov rsp, rbp".to_string());
-        assembly.push("\tpop rbp".to_string());
-        assembly.push("\tret".to_string());
+        Codegen::compile_with(&mut Preprocessor::default(), "<input>", input)
+    }
+
+    // Compile the file `name`, whose contents are `src`.
+    // Errors are rendered by `preprocessor.sources()`, which has every included file.
+    pub fn compile_with(
+        preprocessor: &mut Preprocessor,
+        name: &str,
+        src: &str,
+    ) -> Result<Vec<String>, Vec<CompileError>> {
+        let mut assembly = vec![".intel_syntax noprefix".to_string()];
+        let program = parser::parse(preprocessor, name, src)?;
+        Codegen::new()
+            .gen(&mut assembly, program)
+            .map_err(|e| vec![e])?;
+        // the stack does not need to be executable
+        assembly.push(".section .note.GNU-stack,\"\",@progbits".to_string());
         Ok(assembly)
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use crate::codegen::Codegen;
-//     use crate::errors::{CompileError, CompileErrorType, ParseError};
-
-//     #[test]
-//     fn test_error_parse_not_number_1() {
-//         let code = "+1+22 + 123;";
-//         let out = Codegen::compile(code);
-//         assert!(out.is_ok());
-//         assert_eq!(
-//             out.ok().unwrap(),
-//             vec![
-//                 ".intel_syntax noprefix",
-//                 ".global main",
-//                 "main:",
-//                 "\tpush rbp",
-//                 "\tmov rbp, rsp",
-//                 "\tsub rsp, 208",
-//                 "\tpush 1",
-//                 "\tpush 22",
-//                 "\tpop rdi",
-//                 "\tpop rax",
-//                 "\tadd rax, rdi",
-//                 "\tpush rax",
-//                 "\tpush 123",
-//                 "\tpop rdi",
-//                 "\tpop rax",
-//                 "\tadd rax, rdi",
-//                 "\tpush rax",
-//                 "\tpop rax",
-//                 "\tmov rsp, rbp",
-//                 "\tpop rbp",
-//                 "\tret"
-//             ]
-//             .iter()
-//             .map(|e| e.to_string())
-//             .collect::<Vec<String>>()
-//         );
-//     }
-
-//     #[test]
-//     fn test_error_parse_not_number_2() {
-//         let code = "1++++22 + 123;";
-//         let out = Codegen::compile(code);
-//         assert!(out.is_ok());
-//         assert_eq!(
-//             out.ok().unwrap(),
-//             vec![
-//                 ".intel_syntax noprefix",
-//                 ".global main",
-//                 "main:",
-//                 "\tpush rbp",
-//                 "\tmov rbp, rsp",
-//                 "\tsub rsp, 208",
-//                 "\tpush 1",
-//                 "\tpush 22",
-//                 "\tpop rdi",
-//                 "\tpop rax",
-//                 "\tadd rax, rdi",
-//                 "\tpush rax",
-//                 "\tpush 123",
-//                 "\tpop rdi",
-//                 "\tpop rax",
-//                 "\tadd rax, rdi",
-//                 "\tpush rax",
-//                 "\tpop rax",
-//                 "\tmov rsp, rbp",
-//                 "\tpop rbp",
-//                 "\tret"
-//             ]
-//             .iter()
-//             .map(|e| e.to_string())
-//             .collect::<Vec<String>>()
-//         );
-//     }
-
-//     #[test]
-//     fn test_error_parse_trailing_1() {
-//         let code = "1+ 123+";
-//         let out = Codegen::compile(code);
-//         assert!(out.is_err());
-//         assert_eq!(
-//             out.err().unwrap(),
-//             vec![CompileError {
-//                 error_type: CompileErrorType::Parsing(ParseError::TrailingOp),
-//                 pos: None,
-//             }]
-//         );
-//     }
+#[test]
+fn test_lvalue_error_span() {
+    let errors = Codegen::compile("int main() { int a; (a+1) = 3; return a; }").unwrap_err();
+    assert_eq!(
+        errors,
+        vec![CompileError {
+            error_type: CompileErrorType::Codegen(CodegenError::LValueNotVar),
+            pos: Some(20..25),
+        }]
+    );
+}
 
-//     #[test]
-//     fn test_error_parse_trailing_2() {
-//         let code = "1+ 123-";
-//         let out = Codegen::compile(code);
-//         assert!(out.is_err());
-//         assert_eq!(
-//             out.err().unwrap(),
-//             vec![CompileError {
-//                 error_type: CompileErrorType::Parsing(ParseError::TrailingOp),
-//                 pos: None,
-//             }]
-//         );
-//     }
+#[test]
+fn test_type_error_span() {
+    let errors = Codegen::compile("int main() { int *p; return p + &p; }").unwrap_err();
+    assert_eq!(
+        errors,
+        vec![CompileError {
+            error_type: CompileErrorType::Typing(crate::errors::TypeError::InvalidOperand),
+            pos: Some(28..34),
+        }]
+    );
+}
 
-//     #[test]
-//     fn test_error_parse_not_number_3() {
-//         let code = "1+-22 + 123;";
-//         let out = Codegen::compile(code);
-//         assert!(out.is_ok());
-//         assert_eq!(
-//             out.ok().unwrap(),
-//             vec![
-//                 ".intel_syntax noprefix",
-//                 ".global main",
-//                 "main:",
-//                 "\tpush rbp",
-//                 "\tmov rbp, rsp",
-//                 "\tsub rsp, 208",
-//                 "\tpush 1",
-//                 "\tpush 0",
-//                 "\tpush 22",
-//                 "\tpop rdi",
-//                 "\tpop rax",
-//                 "\tsub rax, rdi",
-//                 "\tpush rax",
-//                 "\tpop rdi",
-//                 "\tpop rax",
-//                 "\tadd rax, rdi",
-//                 "\tpush rax",
-//                 "\tpush 123",
-//                 "\tpop rdi",
-//                 "\tpop rax",
-//                 "\tadd rax, rdi",
-//                 "\tpush rax",
-//                 "\tpop rax",
-//                 "\tmov rsp, rbp",
-//                 "\tpop rbp",
-//                 "\tret"
-//             ]
-//             .iter()
-//             .map(|e| e.to_string())
-//             .collect::<Vec<String>>()
-//         );
-//     }
+#[test]
+fn test_array_too_large() {
+    let errors = Codegen::compile("int main() { int a[2][4611686018427387904]; return 0; }");
+    assert_eq!(
+        errors.unwrap_err(),
+        vec![CompileError {
+            error_type: CompileErrorType::Parsing(crate::errors::ParseError::ArrayTooLarge),
+            pos: Some(22..41),
+        }]
+    );
+}
 
-//     #[test]
-//     fn test_error_parse_cannot() {
-//         let code = "1 3 23;";
-//         let out = Codegen::compile(code);
-//         assert!(out.is_err());
-//         assert_eq!(
-//             out.err().unwrap(),
-//             vec![CompileError {
-//                 error_type: CompileErrorType::Parsing(ParseError::CannotParse),
-//                 pos: Some(2..3),
-//             }]
-//         );
-//     }
+#[test]
+fn test_redefinition() {
+    let src = "int main; int main() { return 0; }\nint f(); int f;\nint g() { return 0; } int g() { return 1; }";
+    let errors = Codegen::compile(src).unwrap_err();
+    use crate::errors::ParseError;
+    assert_eq!(
+        errors,
+        vec![
+            CompileError {
+                error_type: CompileErrorType::Parsing(ParseError::RedefinedKind),
+                pos: Some(14..18),
+            },
+            CompileError {
+                error_type: CompileErrorType::Parsing(ParseError::RedefinedKind),
+                pos: Some(48..49),
+            },
+            CompileError {
+                error_type: CompileErrorType::Parsing(ParseError::RedefinedFunc),
+                pos: Some(77..78),
+            },
+        ]
+    );
+}
 
-//     #[test]
-//     fn test_error_no_semicolon() {
-//         let code = "1 + 2 ";
-//         let out = Codegen::compile(code);
-//         assert!(out.is_err());
-//         assert_eq!(
-//             out.err().unwrap(),
-//             vec![CompileError {
-//                 error_type: CompileErrorType::Parsing(ParseError::NeedSemiColon),
-//                 pos: None,
-//             }]
-//         );
-//     }
+#[test]
+fn test_end_of_input_span() {
+    let src = "int main() { return 1 +";
+    let errors = Codegen::compile(src).unwrap_err();
+    assert_eq!(
+        errors,
+        vec![
+            CompileError {
+                error_type: CompileErrorType::Parsing(crate::errors::ParseError::TrailingOp),
+                pos: Some(src.len()..src.len()),
+            },
+            CompileError {
+                error_type: CompileErrorType::Parsing(
+                    crate::errors::ParseError::NotFoundCurlyBracketR
+                ),
+                pos: Some(src.len()..src.len()),
+            }
+        ]
+    );
+}
 
-//     #[test]
-//     fn test_variable() {
-//         let code = "a=3;a;";
-//         let out = Codegen::compile(code);
-//         assert!(out.is_ok());
-//         assert_eq!(
-//             out.ok().unwrap(),
-//             vec![
-//                 ".intel_syntax noprefix",
-//                 ".global main",
-//                 "main:",
-//                 "\tpush rbp",
-//                 "\tmov rbp, rsp",
-//                 "\tsub rsp, 208",
-//                 "\tmov rax, rbp",
-//                 "\tsub rax, 8",
-//                 "\tpush rax",
-//                 "\tpush 3",
-//                 "\tpop rdi",
-//                 "\tpop rax",
-//                 "\tmov [rax], rdi",
-//                 "\tpush rdi",
-//                 "\tpop rax",
-//                 "\tmov rax, rbp",
-//                 "\tsub rax, 8",
-//                 "\tpush rax",
-//                 "\tpop rax",
-//                 "\tmov rax, [rax]",
-//                 "\tpush rax",
-//                 "\tpop rax",
-//                 "\tmov rsp, rbp",
-//                 "\tpop rbp",
-//                 "\tret"
-//             ]
-//             .iter()
-//             .map(|e| e.to_string())
-//             .collect::<Vec<String>>()
-//         );
-//     }
-// }
+#[test]
+fn test_multiple_errors() {
+    let src = "int main() {
+    int a = ;
+    while (a b) { a = 1; }
+    return a
+}
+int f( { return 0; }
+int g() { return x; }";
+    let errors: Vec<CompileErrorType> = Codegen::compile(src)
+        .unwrap_err()
+        .into_iter()
+        .map(|e| e.error_type)
+        .collect();
+    use crate::errors::ParseError;
+    assert_eq!(
+        errors,
+        vec![
+            CompileErrorType::Parsing(ParseError::NotNumber),
+            CompileErrorType::Parsing(ParseError::NotFoundRoundBracketR),
+            CompileErrorType::Parsing(ParseError::NeedSemiColon),
+            CompileErrorType::Parsing(ParseError::NotType),
+            CompileErrorType::Parsing(ParseError::UndefinedVar),
+        ]
+    );
+}
@@ -0,0 +1,607 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::errors::CompileError;
+use crate::parser::{self, Node, NodeKind, Program};
+use crate::preprocessor::{Preprocessor, SourceMap};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AstFormat {
+    Tree, // indented, one node per line
+    Json, // one JSON object, for tools
+}
+
+// --dump-tokens: the preprocessed tokens of the file `name`, one per line with
+// its kind, text, span and where it comes from
+pub fn tokens(
+    preprocessor: &mut Preprocessor,
+    name: &str,
+    src: &str,
+) -> Result<String, Vec<CompileError>> {
+    let tokens = preprocessor.run(name, src)?;
+    let mut text = String::new();
+    for token in &tokens {
+        let kind = format!("{:?}", token.kind);
+        let quoted = format!("{:?}", token.text);
+        let location = location(preprocessor.sources(), &token.span);
+        writeln!(
+            text,
+            "{:<24} {:<16} {:?} {}",
+            kind, quoted, token.span, location
+        )
+        .unwrap();
+    }
+    Ok(text)
+}
+
+// --dump-ast: the typed AST of the file `name`
+pub fn ast(
+    preprocessor: &mut Preprocessor,
+    name: &str,
+    src: &str,
+    format: AstFormat,
+) -> Result<String, Vec<CompileError>> {
+    let program = parser::parse(preprocessor, name, src)?;
+    Ok(match format {
+        AstFormat::Tree => tree(&program),
+        AstFormat::Json => json(&program, preprocessor.sources()) + "\n",
+    })
+}
+
+// "file:line:column" of the start of the span
+fn location(sources: &SourceMap, span: &Range<usize>) -> String {
+    match sources.origin(span.start) {
+        Some((file, offset)) => {
+            let (name, line, column) = file.location(offset);
+            format!("{}:{}:{}", name, line, column)
+        }
+        None => "?".to_string(),
+    }
+}
+
+// Children of the node with the names of their fields
+fn children(node: &Node) -> Vec<(&'static str, &Node)> {
+    let fields = [
+        ("lhs", &node.lhs),
+        ("rhs", &node.rhs),
+        ("cond", &node.cond),
+        ("then", &node.then),
+        ("els", &node.els),
+        ("init", &node.init),
+        ("inc", &node.inc),
+    ];
+    let mut children: Vec<(&str, &Node)> = fields
+        .into_iter()
+        .filter_map(|(field, child)| Some((field, child.as_deref()?)))
+        .collect();
+    children.extend(node.body.iter().map(|child| ("body", child)));
+    children.extend(node.args.iter().map(|child| ("args", child)));
+    children
+}
+
+// "Add 3..8 int", "FuncCall foo 0..5 int"
+fn node_label(node: &Node) -> String {
+    let mut label = format!("{:?}", node.kind);
+    if let Some(name) = &node.name {
+        write!(label, " {}", name).unwrap();
+    }
+    write!(label, " {:?}", node.span).unwrap();
+    if let Some(ty) = &node.ty {
+        write!(label, " {}", ty).unwrap();
+    }
+    label
+}
+
+fn tree(program: &Program) -> String {
+    let mut text = String::new();
+    for var in &program.globals {
+        write!(text, "global {} {}", var.name, var.ty).unwrap();
+        if let Some(init) = var.init {
+            write!(text, " = {}", init).unwrap();
+        }
+        text.push('\n');
+    }
+    for literal in &program.strings {
+        let data = String::from_utf8_lossy(&literal.data);
+        writeln!(text, "string {} {:?}", literal.name, data).unwrap();
+    }
+    for function in &program.functions {
+        writeln!(
+            text,
+            "function {} {} (stack size {})",
+            function.name, function.return_ty, function.stack_size
+        )
+        .unwrap();
+        for param in &function.params {
+            writeln!(text, "  param {} Var({})", param.ty, param.offset).unwrap();
+        }
+        for node in &function.body {
+            tree_node(&mut text, node, "", 1);
+        }
+    }
+    text
+}
+
+fn tree_node(text: &mut String, node: &Node, field: &str, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let field = if field.is_empty() {
+        String::new()
+    } else {
+        format!("{}: ", field)
+    };
+    writeln!(text, "{}{}{}", indent, field, node_label(node)).unwrap();
+    for (field, child) in children(node) {
+        tree_node(text, child, field, depth + 1);
+    }
+}
+
+fn json(program: &Program, sources: &SourceMap) -> String {
+    let files: Vec<String> = sources
+        .files()
+        .iter()
+        .map(|file| {
+            format!(
+                "{{\"name\":{},\"start\":{}}}",
+                json_string(&file.name),
+                file.start
+            )
+        })
+        .collect();
+    let globals: Vec<String> = program
+        .globals
+        .iter()
+        .map(|var| {
+            let init = var.init.map_or("null".to_string(), |init| init.to_string());
+            format!(
+                "{{\"name\":{},\"type\":{},\"init\":{}}}",
+                json_string(&var.name),
+                json_string(&var.ty.to_string()),
+                init
+            )
+        })
+        .collect();
+    let strings: Vec<String> = program
+        .strings
+        .iter()
+        .map(|literal| {
+            let data: Vec<String> = literal.data.iter().map(|byte| byte.to_string()).collect();
+            format!(
+                "{{\"name\":{},\"data\":[{}]}}",
+                json_string(&literal.name),
+                data.join(",")
+            )
+        })
+        .collect();
+    let functions: Vec<String> = program
+        .functions
+        .iter()
+        .map(|function| {
+            let params: Vec<String> = function
+                .params
+                .iter()
+                .map(|param| {
+                    format!(
+                        "{{\"type\":{},\"offset\":{}}}",
+                        json_string(&param.ty.to_string()),
+                        param.offset
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"name\":{},\"return_type\":{},\"params\":[{}],\"stack_size\":{},\"body\":{}}}",
+                json_string(&function.name),
+                json_string(&function.return_ty.to_string()),
+                params.join(","),
+                function.stack_size,
+                json_nodes(&function.body)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"files\":[{}],\"globals\":[{}],\"strings\":[{}],\"functions\":[{}]}}",
+        files.join(","),
+        globals.join(","),
+        strings.join(","),
+        functions.join(",")
+    )
+}
+
+// Every node has "kind" and "span"; "value" of numbers, "offset" of variables,
+// and the other fields only when they are set
+fn json_node(node: &Node) -> String {
+    let (kind, value) = match node.kind {
+        NodeKind::Var(offset) => ("Var".to_string(), Some(("offset", offset as i64))),
+        NodeKind::Number(value) => ("Number".to_string(), Some(("value", value))),
+        kind => (format!("{:?}", kind), None),
+    };
+    let mut fields = vec![
+        format!("\"kind\":{}", json_string(&kind)),
+        format!("\"span\":[{},{}]", node.span.start, node.span.end),
+    ];
+    if let Some((field, value)) = value {
+        fields.push(format!("\"{}\":{}", field, value));
+    }
+    if let Some(ty) = &node.ty {
+        fields.push(format!("\"type\":{}", json_string(&ty.to_string())));
+    }
+    if let Some(name) = &node.name {
+        fields.push(format!("\"name\":{}", json_string(name)));
+    }
+    for (field, child) in children(node) {
+        if field != "body" && field != "args" {
+            fields.push(format!("\"{}\":{}", field, json_node(child)));
+        }
+    }
+    if !node.body.is_empty() {
+        fields.push(format!("\"body\":{}", json_nodes(&node.body)));
+    }
+    if !node.args.is_empty() {
+        fields.push(format!("\"args\":{}", json_nodes(&node.args)));
+    }
+    format!("{{{}}}", fields.join(","))
+}
+
+fn json_nodes(nodes: &[Node]) -> String {
+    let nodes: Vec<String> = nodes.iter().map(json_node).collect();
+    format!("[{}]", nodes.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// --emit=dot: each function's AST and the control-flow graph of its statements,
+// as one Graphviz graph with a cluster for each
+pub fn dot(
+    preprocessor: &mut Preprocessor,
+    name: &str,
+    src: &str,
+) -> Result<String, Vec<CompileError>> {
+    let program = parser::parse(preprocessor, name, src)?;
+    let sources = preprocessor.sources();
+    let mut text = String::from("digraph program {\n");
+    text += "  node [shape=box, fontname=\"monospace\"];\n";
+    for (index, function) in program.functions.iter().enumerate() {
+        writeln!(text, "  subgraph cluster_ast_{} {{", index).unwrap();
+        writeln!(
+            text,
+            "    label={};",
+            dot_string(&format!("{}: AST", function.name))
+        )
+        .unwrap();
+        let root = format!("a{}", index);
+        let label = format!("function {}", function.name);
+        writeln!(text, "    {} [label={}];", root, dot_string(&label)).unwrap();
+        let mut count = 0;
+        for node in &function.body {
+            let id = dot_node(&mut text, sources, node, &root, &mut count);
+            writeln!(text, "    {} -> {};", root, id).unwrap();
+        }
+        text += "  }\n";
+
+        writeln!(text, "  subgraph cluster_cfg_{} {{", index).unwrap();
+        writeln!(
+            text,
+            "    label={};",
+            dot_string(&format!("{}: CFG", function.name))
+        )
+        .unwrap();
+        let cfg = Cfg::build(sources, function.body.as_slice());
+        // empty blocks which nothing jumps to, like the join of two returning branches
+        let reached: Vec<usize> = cfg
+            .blocks
+            .iter()
+            .flat_map(|block| &block.succs)
+            .map(|(succ, _)| *succ)
+            .collect();
+        let shown =
+            |id: usize| id == ENTRY || !cfg.blocks[id].stmts.is_empty() || reached.contains(&id);
+        for (id, block) in cfg.blocks.iter().enumerate().filter(|(id, _)| shown(*id)) {
+            let name = match id {
+                ENTRY => "entry".to_string(),
+                EXIT => "exit".to_string(),
+                _ => format!("B{}", id),
+            };
+            // left-justified lines
+            let lines: Vec<String> = std::iter::once(&name)
+                .chain(&block.stmts)
+                .map(|line| dot_escape(line) + "\\l")
+                .collect();
+            let label = format!("\"{}\"", lines.concat());
+            writeln!(text, "    c{}_{} [label={}];", index, id, label).unwrap();
+        }
+        for (id, block) in cfg.blocks.iter().enumerate().filter(|(id, _)| shown(*id)) {
+            for (succ, label) in &block.succs {
+                write!(text, "    c{}_{} -> c{}_{}", index, id, index, succ).unwrap();
+                match label {
+                    Some(label) => writeln!(text, " [label={}];", dot_string(label)).unwrap(),
+                    None => text += ";\n",
+                }
+            }
+        }
+        text += "  }\n";
+    }
+    text += "}\n";
+    Ok(text)
+}
+
+// The node and its children with edges named by their fields. Returns the id of the node.
+fn dot_node(
+    text: &mut String,
+    sources: &SourceMap,
+    node: &Node,
+    prefix: &str,
+    count: &mut usize,
+) -> String {
+    let id = format!("{}_{}", prefix, count);
+    *count += 1;
+    // the source text shows operands swapped by the parser, like "a > b" as "b < a"
+    let mut label = node_label(node);
+    if let Some(src) = source_text(sources, &node.span) {
+        label += &format!("\n{}", src);
+    }
+    writeln!(text, "    {} [label={}];", id, dot_string(&label)).unwrap();
+    for (field, child) in children(node) {
+        let child_id = dot_node(text, sources, child, prefix, count);
+        writeln!(
+            text,
+            "    {} -> {} [label={}];",
+            id,
+            child_id,
+            dot_string(field)
+        )
+        .unwrap();
+    }
+    id
+}
+
+// The source of the span on one line, if it is in one file
+fn source_text(sources: &SourceMap, span: &Range<usize>) -> Option<String> {
+    let (file, offset) = sources.origin(span.start)?;
+    let src = file.src.get(offset..offset + span.len())?;
+    Some(src.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn dot_string(s: &str) -> String {
+    format!("\"{}\"", dot_escape(s))
+}
+
+const ENTRY: usize = 0;
+const EXIT: usize = 1;
+
+#[derive(Debug, Default)]
+struct BasicBlock {
+    stmts: Vec<String>,
+    succs: Vec<(usize, Option<&'static str>)>, // successor and the label of the edge
+}
+
+// Basic blocks of statements. Branches inside expressions, like "&&" and "?:",
+// stay in the statement.
+#[derive(Debug)]
+struct Cfg<'a> {
+    sources: &'a SourceMap,
+    blocks: Vec<BasicBlock>,
+    current: Option<usize>,     // None after a jump, until the next statement
+    loops: Vec<(usize, usize)>, // targets of "break" and "continue"
+}
+
+impl<'a> Cfg<'a> {
+    fn build(sources: &'a SourceMap, body: &[Node]) -> Self {
+        let mut cfg = Cfg {
+            sources,
+            blocks: vec![BasicBlock::default(), BasicBlock::default()],
+            current: Some(ENTRY),
+            loops: vec![],
+        };
+        body.iter().for_each(|node| cfg.stmt(node));
+        cfg.jump(EXIT, None);
+        cfg
+    }
+
+    fn new_block(&mut self) -> usize {
+        self.blocks.push(BasicBlock::default());
+        self.blocks.len() - 1
+    }
+
+    // End the current block with an edge to `target`
+    fn jump(&mut self, target: usize, label: Option<&'static str>) {
+        if let Some(current) = self.current.take() {
+            self.blocks[current].succs.push((target, label));
+        }
+    }
+
+    // Statements after a jump start an unreachable block
+    fn push(&mut self, node: &Node) {
+        let current = match self.current {
+            Some(current) => current,
+            None => {
+                let block = self.new_block();
+                self.current = Some(block);
+                block
+            }
+        };
+        let text = source_text(self.sources, &node.span).unwrap_or_else(|| node_label(node));
+        self.blocks[current].stmts.push(text);
+    }
+
+    // Make the condition a block of its own, which branches to `then` and `els`
+    fn branch(&mut self, cond: Option<&Node>, then: usize, els: usize) {
+        match cond {
+            Some(cond) => {
+                self.push(cond);
+                let current = self.current.take().expect("no block");
+                self.blocks[current].succs.push((then, Some("true")));
+                self.blocks[current].succs.push((els, Some("false")));
+            }
+            None => self.jump(then, None),
+        }
+    }
+
+    fn stmt(&mut self, node: &Node) {
+        match node.kind {
+            NodeKind::Block => node.body.iter().for_each(|node| self.stmt(node)),
+            NodeKind::If => {
+                let then = self.new_block();
+                let join = self.new_block();
+                let els = if node.els.is_some() {
+                    self.new_block()
+                } else {
+                    join
+                };
+                self.branch(node.cond.as_deref(), then, els);
+                self.current = Some(then);
+                node.then.iter().for_each(|node| self.stmt(node));
+                self.jump(join, None);
+                if let Some(els_stmt) = &node.els {
+                    self.current = Some(els);
+                    self.stmt(els_stmt);
+                    self.jump(join, None);
+                }
+                self.current = Some(join);
+            }
+            NodeKind::While | NodeKind::For => {
+                if let Some(init) = &node.init {
+                    self.push(init);
+                }
+                let cond = self.new_block();
+                let body = self.new_block();
+                let inc = if node.inc.is_some() {
+                    self.new_block()
+                } else {
+                    cond
+                };
+                let exit = self.new_block();
+                self.jump(cond, None);
+                self.current = Some(cond);
+                self.branch(node.cond.as_deref(), body, exit);
+                self.current = Some(body);
+                self.loops.push((exit, inc));
+                node.then.iter().for_each(|node| self.stmt(node));
+                self.loops.pop();
+                if let Some(inc_expr) = &node.inc {
+                    self.jump(inc, None);
+                    self.current = Some(inc);
+                    self.push(inc_expr);
+                }
+                self.jump(cond, None);
+                self.current = Some(exit);
+            }
+            NodeKind::Return => {
+                self.push(node);
+                self.jump(EXIT, None);
+            }
+            NodeKind::Break | NodeKind::Continue => {
+                self.push(node);
+                let (exit, next) = *self.loops.last().expect("jump outside of a loop");
+                let target = if node.kind == NodeKind::Break {
+                    exit
+                } else {
+                    next
+                };
+                self.jump(target, None);
+            }
+            _ => self.push(node),
+        }
+    }
+}
+
+#[test]
+fn test_dump_tokens() {
+    let src = "#define N 42\nint x = N;";
+    let text = tokens(&mut Preprocessor::default(), "a.c", src).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(
+        lines[0].split_whitespace().collect::<Vec<_>>(),
+        vec!["Int", "\"int\"", "13..16", "a.c:2:1"]
+    );
+    // the expansion of N is placed where N is used
+    assert_eq!(
+        lines[3].split_whitespace().collect::<Vec<_>>(),
+        vec!["Number(42,", "Int)", "\"42\"", "21..22", "a.c:2:9"]
+    );
+}
+
+#[test]
+fn test_dump_ast() {
+    let src = "int main() { return 1 > 2; }";
+    let text = ast(&mut Preprocessor::default(), "a.c", src, AstFormat::Tree).unwrap();
+    assert_eq!(
+        text,
+        "function main int (stack size 0)
+  Return 13..26
+    lhs: Less 20..25 int
+      lhs: Number(2) 24..25 int
+      rhs: Number(1) 20..21 int
+"
+    );
+    let text = ast(&mut Preprocessor::default(), "a.c", src, AstFormat::Json).unwrap();
+    assert!(text.starts_with("{\"files\":[{\"name\":\"a.c\",\"start\":0}],\"globals\":[],"));
+    assert!(text.contains(
+        "\"lhs\":{\"kind\":\"Less\",\"span\":[20,25],\"type\":\"int\",\
+         \"lhs\":{\"kind\":\"Number\",\"span\":[24,25],\"value\":2,\"type\":\"int\"}"
+    ));
+    assert_eq!(json_string("a\"\\\n\u{1}"), "\"a\\\"\\\\\\n\\u0001\"");
+}
+
+#[test]
+fn test_dot() {
+    let src = "int main() { while (1 > 2) break; return 0; }";
+    let text = dot(&mut Preprocessor::default(), "a.c", src).unwrap();
+    assert_eq!(
+        text,
+        r#"digraph program {
+  node [shape=box, fontname="monospace"];
+  subgraph cluster_ast_0 {
+    label="main: AST";
+    a0 [label="function main"];
+    a0_0 [label="While 13..33\nwhile (1 > 2) break;"];
+    a0_1 [label="Less 20..25 int\n1 > 2"];
+    a0_2 [label="Number(2) 24..25 int\n2"];
+    a0_1 -> a0_2 [label="lhs"];
+    a0_3 [label="Number(1) 20..21 int\n1"];
+    a0_1 -> a0_3 [label="rhs"];
+    a0_0 -> a0_1 [label="cond"];
+    a0_4 [label="Break 27..33\nbreak;"];
+    a0_0 -> a0_4 [label="then"];
+    a0 -> a0_0;
+    a0_5 [label="Return 34..43\nreturn 0;"];
+    a0_6 [label="Number(0) 41..42 int\n0"];
+    a0_5 -> a0_6 [label="lhs"];
+    a0 -> a0_5;
+  }
+  subgraph cluster_cfg_0 {
+    label="main: CFG";
+    c0_0 [label="entry\l"];
+    c0_1 [label="exit\l"];
+    c0_2 [label="B2\l1 > 2\l"];
+    c0_3 [label="B3\lbreak;\l"];
+    c0_4 [label="B4\lreturn 0;\l"];
+    c0_0 -> c0_2;
+    c0_2 -> c0_3 [label="true"];
+    c0_2 -> c0_4 [label="false"];
+    c0_3 -> c0_4;
+    c0_4 -> c0_1;
+  }
+}
+"#
+    );
+}
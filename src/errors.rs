@@ -1,3 +1,5 @@
+use std::error;
+use std::fmt;
 use std::ops::Range;
 
 #[derive(Debug, PartialEq)]
@@ -6,36 +8,358 @@ pub struct CompileError {
     pub pos: Option<Range<usize>>,
 }
 
-// impl fmt::Display for CompileError<'a> {
-//     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-//         write!(f, "{:?} {:?}", self.error_type, self.pos)
-//     }
-// }
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error_type)
+    }
+}
+
+impl error::Error for CompileError {}
+
+impl CompileError {
+    // Render the error like gcc/clang:
+    //
+    // foo.c:1:11: error: expected ';'
+    //  1 | int x = 3 return x;
+    //    |           ^~~~~~
+    pub fn render(&self, filename: &str, src: &str) -> String {
+        self.render_at(filename, src, self.pos.as_ref())
+    }
 
-// impl error::Error for CompileError<'_> {}
+    // Render the error at `pos` of `src`, which may be a file other than the main one
+    pub fn render_at(&self, filename: &str, src: &str, pos: Option<&Range<usize>>) -> String {
+        let line = pos
+            .filter(|pos| pos.start <= src.len())
+            .map_or(0, |pos| line_column(src, pos.start).0);
+        self.render_as(filename, line, src, pos)
+    }
+
+    // Render the error at `pos` of `src`, which is told to be `line` of `filename`
+    // by '#line', so that only the source line itself is taken from `src`
+    pub fn render_as(
+        &self,
+        filename: &str,
+        line: usize,
+        src: &str,
+        pos: Option<&Range<usize>>,
+    ) -> String {
+        let pos = match pos {
+            Some(pos) if pos.start <= src.len() => pos,
+            _ => return format!("{}: error: {}", filename, self),
+        };
+        let (_, column) = line_column(src, pos.start);
+        let line_start = src[..pos.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[pos.start..]
+            .find('\n')
+            .map_or(src.len(), |i| pos.start + i);
+        let source_line = &src[line_start..line_end];
+
+        // keep tabs so that the marker is aligned with the source line
+        let indent: String = src[line_start..pos.start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = src[pos.start..pos.end.clamp(pos.start, line_end)]
+            .chars()
+            .count();
+        let marker = format!("^{}", "~".repeat(width.saturating_sub(1)));
+
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "{}:{}:{}: error: {}\n {} | {}\n {} | {}{}",
+            filename, line, column, self, line, source_line, gutter, indent, marker
+        )
+    }
+}
+
+// 1-based line and column of the byte position `pos`
+pub fn line_column(src: &str, pos: usize) -> (usize, usize) {
+    let before = &src[..pos];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
 
 #[derive(PartialEq, Debug)]
 pub enum CompileErrorType {
     Tokenizing(TokenizeError),
+    Preprocessing(PreprocessError),
     Parsing(ParseError),
+    Typing(TypeError),
     Codegen(CodegenError),
+    Running(RuntimeError),
+}
+
+impl fmt::Display for CompileErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileErrorType::Tokenizing(e) => write!(f, "{}", e),
+            CompileErrorType::Preprocessing(e) => write!(f, "{}", e),
+            CompileErrorType::Parsing(e) => write!(f, "{}", e),
+            CompileErrorType::Typing(e) => write!(f, "{}", e),
+            CompileErrorType::Codegen(e) => write!(f, "{}", e),
+            CompileErrorType::Running(e) => write!(f, "{}", e),
+        }
+    }
 }
 
 #[derive(PartialEq, Debug)]
-pub struct TokenizeError(pub String);
+pub enum TokenizeError {
+    Unknown(String),     // text which is not a token
+    UnterminatedString,  // missing closing '"'
+    UnterminatedChar,    // missing closing '\''
+    UnterminatedComment, // missing closing '*/'
+    InvalidEscape,       // escape sequence out of range or without digits
+    InvalidChar,         // character literal is empty or has more than one byte
+    InvalidNumber,       // bad digit or suffix in an integer literal
+    NumberOutOfRange,    // integer literal does not fit in any type
+}
+
+impl fmt::Display for TokenizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenizeError::Unknown(text) => write!(f, "unknown token '{}'", text),
+            TokenizeError::UnterminatedString => write!(f, "missing terminating '\"' character"),
+            TokenizeError::UnterminatedChar => write!(f, "missing terminating ' character"),
+            TokenizeError::UnterminatedComment => write!(f, "unterminated comment"),
+            TokenizeError::InvalidEscape => write!(f, "invalid escape sequence"),
+            TokenizeError::InvalidChar => {
+                write!(f, "character literal must contain exactly one character")
+            }
+            TokenizeError::InvalidNumber => write!(f, "invalid integer literal"),
+            TokenizeError::NumberOutOfRange => {
+                write!(
+                    f,
+                    "integer literal is too large to be represented in any integer type"
+                )
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum PreprocessError {
+    InvalidDirective,         // unknown name after '#'
+    NoMacroName,              // '#define' or '#undef' without a name
+    ExpectedFilename,         // '#include' without "FILENAME" or <FILENAME>
+    FileNotFound(String),     // included file is in no search path
+    CannotRead(String),       // included file cannot be read
+    IncludeTooDeep,           // recursive '#include'
+    InvalidParams,            // bad parameter list of a function-like macro
+    PasteAtEdge,              // '##' at the start or the end of a macro
+    InvalidPaste(String),     // '##' made text which is not one token
+    NotParam,                 // '#' not followed by a parameter
+    WrongArgCount(String),    // macro invoked with a wrong number of arguments
+    UnterminatedArgs(String), // missing ')' of a macro invocation
+    Unmatched(String),        // '#else', '#elif' or '#endif' without '#if'
+    UnterminatedCondition,    // '#if' without '#endif'
+    Error(String),            // '#error' with its message
+    InvalidLine,              // '#line' or a line marker without a line number
+    AfterElse(String),        // '#else' or '#elif' after '#else'
+    InvalidCondition,         // '#if' or '#elif' with an operator which is not allowed
+    DivisionByZero,           // division or remainder by zero in '#if' or '#elif'
+    ConditionOverflow,        // signed overflow or a bad shift count in '#if' or '#elif'
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreprocessError::InvalidDirective => write!(f, "invalid preprocessing directive"),
+            PreprocessError::NoMacroName => write!(f, "macro name must be an identifier"),
+            PreprocessError::ExpectedFilename => write!(f, "expected \"FILENAME\" or <FILENAME>"),
+            PreprocessError::FileNotFound(name) => write!(f, "'{}' file not found", name),
+            PreprocessError::CannotRead(name) => write!(f, "cannot read '{}'", name),
+            PreprocessError::IncludeTooDeep => write!(f, "#include nested too deeply"),
+            PreprocessError::InvalidParams => write!(f, "invalid macro parameter list"),
+            PreprocessError::PasteAtEdge => {
+                write!(f, "'##' cannot appear at either end of a macro expansion")
+            }
+            PreprocessError::InvalidPaste(text) => write!(
+                f,
+                "pasting formed '{}', an invalid preprocessing token",
+                text
+            ),
+            PreprocessError::NotParam => write!(f, "'#' is not followed by a macro parameter"),
+            PreprocessError::WrongArgCount(name) => {
+                write!(f, "wrong number of arguments to macro '{}'", name)
+            }
+            PreprocessError::UnterminatedArgs(name) => {
+                write!(f, "unterminated argument list invoking macro '{}'", name)
+            }
+            PreprocessError::Unmatched(directive) => write!(f, "#{} without #if", directive),
+            PreprocessError::UnterminatedCondition => {
+                write!(f, "unterminated conditional directive")
+            }
+            PreprocessError::Error(message) => write!(f, "#error {}", message),
+            PreprocessError::AfterElse(directive) => write!(f, "#{} after #else", directive),
+            PreprocessError::InvalidLine => {
+                write!(f, "#line requires a line number and an optional file name")
+            }
+            PreprocessError::InvalidCondition => write!(f, "invalid expression in #if"),
+            PreprocessError::DivisionByZero => write!(f, "division by zero in #if"),
+            PreprocessError::ConditionOverflow => write!(f, "integer overflow in #if"),
+        }
+    }
+}
 
 #[derive(PartialEq, Debug)]
 pub enum ParseError {
     NotNumber,
     TrailingOp,
     CannotParse,
+    NotFoundRoundBracketL,
     NotFoundRoundBracketR,
+    NotFoundCurlyBracketL,
+    NotFoundCurlyBracketR,
+    NotFoundSquareBracketR,
+    NotIdent,
+    NotType,       // type name is expected
+    UndefinedVar,  // variable is not declared
+    RedefinedVar,  // variable is declared twice in a scope
+    RedefinedFunc, // function is defined twice
+    RedefinedKind, // global variable and function of the same name
+    NotConstant,   // initializer of a global variable is not a constant
     NeedSemiColon,
+    NeedColon, // ':' of the conditional operator
     Empty,
+    NotInLoop,     // 'break' or 'continue' outside of a loop
+    TooManyArgs,   // more than 6 arguments or parameters
+    ArrayTooLarge, // size of an array type does not fit in isize
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ParseError::NotNumber => "expected an expression",
+            ParseError::TrailingOp => "expected an expression at end of input",
+            ParseError::CannotParse => "expected an operator",
+            ParseError::NotFoundRoundBracketL => "expected '('",
+            ParseError::NotFoundRoundBracketR => "expected ')'",
+            ParseError::NotFoundCurlyBracketL => "expected '{'",
+            ParseError::NotFoundCurlyBracketR => "expected '}'",
+            ParseError::NotFoundSquareBracketR => "expected ']'",
+            ParseError::NotIdent => "expected an identifier",
+            ParseError::NotType => "expected a type name",
+            ParseError::UndefinedVar => "use of undeclared identifier",
+            ParseError::RedefinedVar => "redefinition of variable",
+            ParseError::RedefinedFunc => "redefinition of function",
+            ParseError::RedefinedKind => "redefinition as a different kind of symbol",
+            ParseError::NotConstant => "initializer is not a constant expression",
+            ParseError::NeedSemiColon => "expected ';'",
+            ParseError::NeedColon => "expected ':'",
+            ParseError::Empty => "expected a statement",
+            ParseError::NotInLoop => "'break' or 'continue' statement not in loop",
+            ParseError::TooManyArgs => "too many arguments or parameters (at most 6)",
+            ParseError::ArrayTooLarge => "array is too large",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum TypeError {
+    InvalidOperand, // operand type is not allowed for the operator
+    NotPointer,     // dereference of a non-pointer
+    NotLValue,      // assignment to an array
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            TypeError::InvalidOperand => "invalid operand types for the operator",
+            TypeError::NotPointer => "cannot dereference a non-pointer",
+            TypeError::NotLValue => "array is not assignable",
+        };
+        write!(f, "{}", message)
+    }
 }
 
 #[derive(PartialEq, Debug)]
 pub enum CodegenError {
-    LValueNotVar,   // left value is not variable
+    LValueNotVar,   // left value is neither a variable nor a dereference
     RValueNotFound, // assign error
+    NotExpression,  // statement in place of an expression
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            CodegenError::LValueNotVar => "expression is not assignable",
+            CodegenError::RValueNotFound => "missing value to assign",
+            CodegenError::NotExpression => "statement is not an expression",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+// Errors of `Interpreter`, which are found only by running the program
+#[derive(PartialEq, Debug)]
+pub enum RuntimeError {
+    NoMain,            // "main" is not defined
+    UndefinedFunction, // call to a function which is not in the program
+    DivisionByZero,    // '/' or '%' by zero
+    InvalidAddress,    // access outside of the variables
+    StackOverflow,     // too deep recursion
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            RuntimeError::NoMain => "undefined reference to 'main'",
+            RuntimeError::UndefinedFunction => "call to a function which is not defined",
+            RuntimeError::DivisionByZero => "division by zero",
+            RuntimeError::InvalidAddress => "invalid memory access",
+            RuntimeError::StackOverflow => "stack overflow",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[test]
+fn test_line_column() {
+    let src = "ab\ncd\n\nef";
+    assert_eq!(line_column(src, 0), (1, 1));
+    assert_eq!(line_column(src, 1), (1, 2));
+    assert_eq!(line_column(src, 3), (2, 1));
+    assert_eq!(line_column(src, 7), (4, 1));
+    assert_eq!(line_column(src, 8), (4, 2));
+}
+
+#[test]
+fn test_render() {
+    let src = "int main() {\n  return 1 2;\n}";
+    let error = CompileError {
+        error_type: CompileErrorType::Parsing(ParseError::NeedSemiColon),
+        pos: Some(24..25),
+    };
+    assert_eq!(
+        error.render("foo.c", src),
+        "foo.c:2:12: error: expected ';'\n 2 |   return 1 2;\n   |            ^"
+    );
+}
+
+#[test]
+fn test_render_underline() {
+    let src = "\tx = foo + 1;";
+    let error = CompileError {
+        error_type: CompileErrorType::Parsing(ParseError::UndefinedVar),
+        pos: Some(5..8),
+    };
+    assert_eq!(
+        error.render("-", src),
+        "-:1:6: error: use of undeclared identifier\n 1 | \tx = foo + 1;\n   | \t    ^~~"
+    );
+}
+
+#[test]
+fn test_render_without_pos() {
+    let error = CompileError {
+        error_type: CompileErrorType::Parsing(ParseError::TrailingOp),
+        pos: None,
+    };
+    assert_eq!(
+        error.render("foo.c", "1+"),
+        "foo.c: error: expected an expression at end of input"
+    );
 }
@@ -0,0 +1,470 @@
+use std::collections::HashMap;
+use std::thread;
+
+use crate::errors::{CodegenError, CompileError, CompileErrorType, RuntimeError};
+use crate::parser::{self, Function, Node, NodeKind, Program};
+use crate::preprocessor::Preprocessor;
+use crate::types::{Operation, Type};
+
+// Addresses start from here, so that a null pointer is never valid
+const BASE: usize = 0x1000;
+// the default stack limit of Linux, which compiled programs run with
+const STACK_SIZE: usize = 8 << 20;
+// each call pushes the return address and rbp, like the code of `Codegen`
+const CALL_FRAME_SIZE: usize = 16;
+// Calls are nested in the Rust stack of a thread as large as the stack of the program,
+// with a margin kept free for a call to go on. Deeper calls go on in a new thread.
+const THREAD_STACK_SIZE: usize = STACK_SIZE + THREAD_STACK_MARGIN;
+const THREAD_STACK_MARGIN: usize = 1 << 20;
+// threads nested for deep calls, which bound the memory of the Rust stacks
+const MAX_THREADS: usize = 64;
+
+// how a statement finished
+enum Flow {
+    Normal,
+    Return(i64),
+    Break,
+    Continue,
+}
+
+// Evaluate a program without assembling it, with the same results as the code of `Codegen`
+#[derive(Debug)]
+pub struct Interpreter<'a> {
+    functions: HashMap<&'a str, &'a Function>,
+    globals: HashMap<&'a str, usize>, // address of global variables and string literals
+    memory: Vec<u8>,                  // data, followed by the stack
+    stack_limit: usize,               // lowest address of the stack
+    rbp: usize,                       // base of the current frame
+    sp: usize,                        // end of the used stack
+    host_stack: usize,                // address of the Rust stack where the thread started
+    threads: usize,                   // number of nested threads running the calls
+}
+
+fn runtime_error(error: RuntimeError, node: Option<&Node>) -> CompileError {
+    CompileError {
+        error_type: CompileErrorType::Running(error),
+        pos: node.map(|node| node.span.clone()),
+    }
+}
+
+fn operand(child: &Option<Box<Node>>) -> &Node {
+    child.as_deref().expect("missing operand")
+}
+
+fn node_type(node: &Node) -> &Type {
+    node.ty.as_ref().expect("expression is not typed")
+}
+
+// results narrower than 64 bits wrap around
+fn truncate(ty: Option<&Type>, value: i64) -> i64 {
+    match ty {
+        Some(Type::Char) => value as i8 as i64,
+        Some(Type::Int) => value as i32 as i64,
+        Some(Type::UInt) => value as u32 as i64,
+        _ => value,
+    }
+}
+
+// An address in the current Rust stack, which grows down
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(program: &'a Program) -> Self {
+        let mut memory = vec![];
+        let mut globals = HashMap::new();
+        for var in &program.globals {
+            let start = memory.len().next_multiple_of(var.ty.align());
+            let size = var.ty.size();
+            memory.resize(start + size, 0);
+            if let Some(init) = var.init {
+                memory[start..].copy_from_slice(&init.to_le_bytes()[..size]);
+            }
+            globals.insert(var.name.as_str(), BASE + start);
+        }
+        for string in &program.strings {
+            globals.insert(string.name.as_str(), BASE + memory.len());
+            memory.extend_from_slice(&string.data);
+        }
+        let stack_start = memory.len().next_multiple_of(16);
+        memory.resize(stack_start + STACK_SIZE, 0);
+        let top = BASE + memory.len();
+        Interpreter {
+            functions: program
+                .functions
+                .iter()
+                .map(|function| (function.name.as_str(), function))
+                .collect(),
+            globals,
+            memory,
+            stack_limit: BASE + stack_start,
+            rbp: top,
+            sp: top,
+            host_stack: 0,
+            threads: 0,
+        }
+    }
+
+    // Run `main` of the program, and return its value
+    pub fn run(input: &str) -> Result<i64, Vec<CompileError>> {
+        Interpreter::run_with(&mut Preprocessor::default(), "<input>", input)
+    }
+
+    // Run the file `name`, whose contents are `src`, like `Codegen::compile_with`
+    pub fn run_with(
+        preprocessor: &mut Preprocessor,
+        name: &str,
+        src: &str,
+    ) -> Result<i64, Vec<CompileError>> {
+        let program = parser::parse(preprocessor, name, src)?;
+        let mut interpreter = Interpreter::new(&program);
+        let main = interpreter
+            .functions
+            .get("main")
+            .copied()
+            .ok_or_else(|| vec![runtime_error(RuntimeError::NoMain, None)])?;
+        interpreter
+            .call_in_thread(main, &[], None)
+            .map_err(|e| vec![e])
+    }
+
+    // Call the function in a new thread, so that its calls have a fresh Rust stack
+    fn call_in_thread(
+        &mut self,
+        function: &'a Function,
+        args: &[i64],
+        node: Option<&Node>,
+    ) -> Result<i64, CompileError> {
+        if self.threads == MAX_THREADS {
+            return Err(runtime_error(RuntimeError::StackOverflow, node));
+        }
+        let host_stack = self.host_stack;
+        self.threads += 1;
+        let result = thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(THREAD_STACK_SIZE)
+                .spawn_scoped(scope, || {
+                    self.host_stack = stack_address();
+                    self.call(function, args, node)
+                })
+                .map(|thread| thread.join())
+        });
+        self.threads -= 1;
+        self.host_stack = host_stack;
+        match result {
+            Ok(Ok(value)) => value,
+            Ok(Err(panic)) => std::panic::resume_unwind(panic),
+            // no more memory for a stack
+            Err(_) => Err(runtime_error(RuntimeError::StackOverflow, node)),
+        }
+    }
+
+    fn call(
+        &mut self,
+        function: &'a Function,
+        args: &[i64],
+        node: Option<&Node>,
+    ) -> Result<i64, CompileError> {
+        let frame = CALL_FRAME_SIZE + function.stack_size;
+        if self.sp - self.stack_limit < frame {
+            return Err(runtime_error(RuntimeError::StackOverflow, node));
+        }
+        if self.host_stack - stack_address() > STACK_SIZE {
+            return self.call_in_thread(function, args, node);
+        }
+        let (rbp, sp) = (self.rbp, self.sp);
+        self.rbp = self.sp - CALL_FRAME_SIZE;
+        self.sp -= frame;
+        for (param, &arg) in function.params.iter().zip(args) {
+            self.store(&param.ty, (self.rbp - param.offset) as i64, arg, node)?;
+        }
+        let mut value = 0; // falling off the end returns 0
+        for stmt in &function.body {
+            if let Flow::Return(result) = self.exec(stmt)? {
+                value = result;
+                break;
+            }
+        }
+        self.rbp = rbp;
+        self.sp = sp;
+        Ok(value)
+    }
+
+    // index of `size` bytes at `addr` in the memory
+    fn index(&self, addr: i64, size: usize, node: Option<&Node>) -> Result<usize, CompileError> {
+        usize::try_from(addr)
+            .ok()
+            .and_then(|addr| addr.checked_sub(BASE))
+            .filter(|index| index.saturating_add(size) <= self.memory.len())
+            .ok_or_else(|| runtime_error(RuntimeError::InvalidAddress, node))
+    }
+
+    fn load(&self, ty: &Type, addr: i64, node: &Node) -> Result<i64, CompileError> {
+        // an array is not loaded, its address is used as a pointer
+        if let Type::Array(..) = ty {
+            return Ok(addr);
+        }
+        let size = ty.size();
+        let index = self.index(addr, size, Some(node))?;
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(&self.memory[index..index + size]);
+        let value = i64::from_le_bytes(bytes);
+        Ok(match ty {
+            Type::Char => value as i8 as i64,
+            Type::Int => value as i32 as i64,
+            _ => value,
+        })
+    }
+
+    fn store(
+        &mut self,
+        ty: &Type,
+        addr: i64,
+        value: i64,
+        node: Option<&Node>,
+    ) -> Result<(), CompileError> {
+        let size = ty.size();
+        let index = self.index(addr, size, node)?;
+        self.memory[index..index + size].copy_from_slice(&value.to_le_bytes()[..size]);
+        Ok(())
+    }
+
+    fn exec(&mut self, node: &'a Node) -> Result<Flow, CompileError> {
+        match node.kind {
+            NodeKind::Return => Ok(Flow::Return(self.eval(operand(&node.lhs))?)),
+            NodeKind::If => {
+                if self.eval(operand(&node.cond))? != 0 {
+                    self.exec(operand(&node.then))
+                } else if let Some(els) = &node.els {
+                    self.exec(els)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            NodeKind::While | NodeKind::For => {
+                if let Some(init) = &node.init {
+                    self.eval(init)?;
+                }
+                loop {
+                    if let Some(cond) = &node.cond {
+                        if self.eval(cond)? == 0 {
+                            break;
+                        }
+                    }
+                    match self.exec(operand(&node.then))? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                    if let Some(inc) = &node.inc {
+                        self.eval(inc)?;
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            NodeKind::Block => {
+                for stmt in &node.body {
+                    match self.exec(stmt)? {
+                        Flow::Normal => {}
+                        flow => return Ok(flow),
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            NodeKind::Break => Ok(Flow::Break),
+            NodeKind::Continue => Ok(Flow::Continue),
+            _ => {
+                self.eval(node)?;
+                Ok(Flow::Normal)
+            }
+        }
+    }
+
+    // address of a left value
+    fn addr(&mut self, node: &'a Node) -> Result<i64, CompileError> {
+        match node.kind {
+            NodeKind::Var(offset) => Ok((self.rbp - offset) as i64),
+            NodeKind::GlobalVar => {
+                let name = node.name.as_deref().expect("global variable without name");
+                Ok(self.globals[name] as i64)
+            }
+            NodeKind::Deref => self.eval(operand(&node.lhs)),
+            _ => Err(CompileError {
+                error_type: CompileErrorType::Codegen(CodegenError::LValueNotVar),
+                pos: Some(node.span.clone()),
+            }),
+        }
+    }
+
+    fn eval(&mut self, node: &'a Node) -> Result<i64, CompileError> {
+        match node.kind {
+            NodeKind::Number(num) => Ok(num),
+            NodeKind::Var(_) | NodeKind::GlobalVar | NodeKind::Deref => {
+                let addr = self.addr(node)?;
+                self.load(node_type(node), addr, node)
+            }
+            NodeKind::Addr => self.addr(operand(&node.lhs)),
+            NodeKind::FuncCall => {
+                let args = node
+                    .args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<i64>, CompileError>>()?;
+                let name = node.name.as_deref().expect("function call without name");
+                let function =
+                    self.functions.get(name).copied().ok_or_else(|| {
+                        runtime_error(RuntimeError::UndefinedFunction, Some(node))
+                    })?;
+                let value = self.call(function, &args, Some(node))?;
+                Ok(truncate(node.ty.as_ref(), value))
+            }
+            // the value of an assignment is the value converted to the type of the target
+            NodeKind::Assign => {
+                let ty = node_type(node);
+                let addr = self.addr(operand(&node.lhs))?;
+                let value = self.eval(operand(&node.rhs))?;
+                self.store(ty, addr, value, Some(node))?;
+                Ok(truncate(Some(ty), value))
+            }
+            NodeKind::AssignOp | NodeKind::PostAssignOp => {
+                let ty = node_type(node);
+                let operation = operand(&node.lhs);
+                let value = operand(&operation.rhs);
+                let addr = self.addr(operand(&operation.lhs))?;
+                let old = self.load(ty, addr, node)?;
+                let rhs = self.eval(value)?;
+                let new = Self::binary(operation, Some(ty), value.ty.as_ref(), old, rhs)?;
+                self.store(ty, addr, new, Some(node))?;
+                Ok(if node.kind == NodeKind::PostAssignOp {
+                    old
+                } else {
+                    truncate(Some(ty), new)
+                })
+            }
+            NodeKind::Cond => {
+                if self.eval(operand(&node.cond))? != 0 {
+                    self.eval(operand(&node.then))
+                } else {
+                    self.eval(operand(&node.els))
+                }
+            }
+            NodeKind::Comma => {
+                self.eval(operand(&node.lhs))?;
+                self.eval(operand(&node.rhs))
+            }
+            NodeKind::LogAnd => Ok(
+                (self.eval(operand(&node.lhs))? != 0 && self.eval(operand(&node.rhs))? != 0) as i64
+            ),
+            NodeKind::LogOr => Ok(
+                (self.eval(operand(&node.lhs))? != 0 || self.eval(operand(&node.rhs))? != 0) as i64
+            ),
+            NodeKind::Not => Ok((self.eval(operand(&node.lhs))? == 0) as i64),
+            NodeKind::BitNot => {
+                let value = self.eval(operand(&node.lhs))?;
+                Ok(truncate(node.ty.as_ref(), !value))
+            }
+            _ => {
+                let (lhs, rhs) = (operand(&node.lhs), operand(&node.rhs));
+                let lhs_value = self.eval(lhs)?;
+                let rhs_value = self.eval(rhs)?;
+                Self::binary(node, lhs.ty.as_ref(), rhs.ty.as_ref(), lhs_value, rhs_value)
+            }
+        }
+    }
+
+    // the operator `node.kind` applied to the two values, like `Codegen::gen_binary`
+    fn binary(
+        node: &Node,
+        lhs_ty: Option<&Type>,
+        rhs_ty: Option<&Type>,
+        lhs: i64,
+        rhs: i64,
+    ) -> Result<i64, CompileError> {
+        let operation = Operation::new(node, lhs_ty, rhs_ty);
+        let (l, r) = if operation.operand_ty == Some(Type::UInt) {
+            (lhs as u32 as i64, rhs as u32 as i64)
+        } else {
+            (lhs, rhs)
+        };
+        let lhs_scale = operation.lhs_scale.map(|size| size as i64);
+        let rhs_scale = operation.rhs_scale.map(|size| size as i64);
+        let value = match node.kind {
+            NodeKind::Add => match (lhs_scale, rhs_scale) {
+                (Some(size), _) => l.wrapping_add(r.wrapping_mul(size)),
+                (_, Some(size)) => l.wrapping_mul(size).wrapping_add(r),
+                _ => l.wrapping_add(r),
+            },
+            NodeKind::Sub => match (lhs_scale, rhs_scale) {
+                (Some(size), Some(_)) => l.wrapping_sub(r) / size,
+                (Some(size), None) => l.wrapping_sub(r.wrapping_mul(size)),
+                _ => l.wrapping_sub(r),
+            },
+            NodeKind::Mul => l.wrapping_mul(r),
+            NodeKind::Div | NodeKind::Mod if r == 0 => {
+                return Err(runtime_error(RuntimeError::DivisionByZero, Some(node)))
+            }
+            NodeKind::Div if operation.unsigned => ((l as u64) / (r as u64)) as i64,
+            NodeKind::Div => l.wrapping_div(r),
+            NodeKind::Mod if operation.unsigned => ((l as u64) % (r as u64)) as i64,
+            NodeKind::Mod => l.wrapping_rem(r),
+            NodeKind::BitAnd => l & r,
+            NodeKind::BitOr => l | r,
+            NodeKind::BitXor => l ^ r,
+            // the count is masked to 6 bits, like the "cl" operand of x86
+            NodeKind::Shl => l.wrapping_shl(r as u32),
+            NodeKind::Shr if operation.unsigned => (l as u64).wrapping_shr(r as u32) as i64,
+            NodeKind::Shr => l.wrapping_shr(r as u32),
+            NodeKind::Eq => (l == r) as i64,
+            NodeKind::NotEq => (l != r) as i64,
+            NodeKind::Less if operation.unsigned => ((l as u64) < (r as u64)) as i64,
+            NodeKind::Less => (l < r) as i64,
+            NodeKind::LessEq if operation.unsigned => ((l as u64) <= (r as u64)) as i64,
+            NodeKind::LessEq => (l <= r) as i64,
+            // statements are not evaluated by eval
+            _ => {
+                return Err(CompileError {
+                    error_type: CompileErrorType::Codegen(CodegenError::NotExpression),
+                    pos: Some(node.span.clone()),
+                })
+            }
+        };
+        Ok(truncate(operation.result_ty.as_ref(), value))
+    }
+}
+
+#[test]
+fn test_run() {
+    let src = "int g[3]; int f(int n) { return n <= 1 ? 1 : n * f(n - 1); }
+int main() { char *s = \"abc\"; g[2] = f(5); return g[2] + s[1] - 'b'; }";
+    assert_eq!(Interpreter::run(src), Ok(120));
+    let src = "int s(int n) { if (n == 0) return 0; return n + s(n - 1); }
+int main() { return s(20000) / 10000; }";
+    assert_eq!(Interpreter::run(src), Ok(20001));
+}
+
+#[test]
+fn test_runtime_errors() {
+    let error_type = |src| Interpreter::run(src).unwrap_err().remove(0).error_type;
+    assert_eq!(
+        error_type("int f() { return 0; }"),
+        CompileErrorType::Running(RuntimeError::NoMain)
+    );
+    assert_eq!(
+        error_type("int main() { return foo(); }"),
+        CompileErrorType::Running(RuntimeError::UndefinedFunction)
+    );
+    assert_eq!(
+        error_type("int main() { int a = 0; return 1 / a; }"),
+        CompileErrorType::Running(RuntimeError::DivisionByZero)
+    );
+    assert_eq!(
+        error_type("int main() { int *p = 0; return *p; }"),
+        CompileErrorType::Running(RuntimeError::InvalidAddress)
+    );
+    assert_eq!(
+        error_type("int f() { return f(); } int main() { return f(); }"),
+        CompileErrorType::Running(RuntimeError::StackOverflow)
+    );
+}
@@ -1,4 +1,8 @@
 pub mod codegen;
-mod errors;
+pub mod dump;
+pub mod errors;
+pub mod interpreter;
 mod parser;
-mod tokenizer;
+pub mod preprocessor;
+pub mod tokenizer;
+mod types;
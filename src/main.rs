@@ -1,22 +1,286 @@
 use std::env;
-use std::process;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
 
+use ::r9cc::dump::{self, AstFormat};
+use ::r9cc::errors::{CompileError, CompileErrorType, ParseError};
+use ::r9cc::interpreter::Interpreter;
+use ::r9cc::preprocessor::{Preprocessor, SourceMap};
 use r9cc::codegen::Codegen as r9cc;
 
+const USAGE: &str =
+    "usage  : ./r9cc [-E|-S] [-o <output>] [-I <dir>] [-D <name>[=<value>]] [-U <name>] <file>
+         ./r9cc [-I <dir>] [-D <name>[=<value>]] [-U <name>] --run <program>
+  <file>           C source file, or '-' to read from stdin
+  -E               stop after preprocessing (default output: stdout)
+  -S               stop after generating assembly (default output: <file>.s)
+  -o <output>      write output to <output> ('-' is stdout)
+  -I <dir>         search <dir> for #include files
+  -D <name>        define <name> as 1, or as <value> with <name>=<value>
+  -U <name>        undefine <name>
+  --run <program>  interpret the program, or statements as the body of main,
+                   and exit with the value of main
+  --dump-tokens    print the preprocessed tokens (default output: stdout)
+  --dump-ast[=<format>]
+                   print the typed AST as 'tree' (default) or 'json'
+  --emit=dot       print the AST and control-flow graph of each function
+                   as Graphviz DOT (default output: stdout)
+example: ./r9cc -o foo foo.c";
+
+#[derive(Debug)]
+enum MacroOption {
+    Define(String),   // -D
+    Undefine(String), // -U
+}
+
+// Outputs which stop the compilation and print text
+#[derive(Debug)]
+enum Dump {
+    Preprocessed,   // -E
+    Tokens,         // --dump-tokens
+    Ast(AstFormat), // --dump-ast
+    Dot,            // --emit=dot
+}
+
+#[derive(Debug)]
+struct Options {
+    input: String,
+    output: Option<String>,
+    dump: Option<Dump>,
+    assembly_only: bool,         // -S
+    run: Option<String>,         // --run <program>
+    include_paths: Vec<PathBuf>, // -I
+    macros: Vec<MacroOption>,    // -D and -U, in order
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("r9cc: {}", message);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Options {
+    let mut input = None;
+    let mut output = None;
+    let mut dump = None;
+    let mut assembly_only = false;
+    let mut run = None;
+    let mut include_paths = vec![];
+    let mut macros = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-E" => dump = Some(Dump::Preprocessed),
+            "--dump-tokens" => dump = Some(Dump::Tokens),
+            "--dump-ast" | "--dump-ast=tree" => dump = Some(Dump::Ast(AstFormat::Tree)),
+            "--dump-ast=json" => dump = Some(Dump::Ast(AstFormat::Json)),
+            "--emit=dot" => dump = Some(Dump::Dot),
+            "-S" => assembly_only = true,
+            "-o" => match args.next() {
+                Some(path) => output = Some(path),
+                None => usage_error("missing filename after '-o'"),
+            },
+            "-I" => match args.next() {
+                Some(dir) => include_paths.push(PathBuf::from(dir)),
+                None => usage_error("missing directory after '-I'"),
+            },
+            "-D" => match args.next() {
+                Some(definition) => macros.push(MacroOption::Define(definition)),
+                None => usage_error("missing macro name after '-D'"),
+            },
+            "-U" => match args.next() {
+                Some(name) => macros.push(MacroOption::Undefine(name)),
+                None => usage_error("missing macro name after '-U'"),
+            },
+            "--run" => match args.next() {
+                Some(program) => run = Some(program),
+                None => usage_error("missing program after '--run'"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with("-o") => output = Some(arg[2..].to_string()),
+            _ if arg.starts_with("-I") => include_paths.push(PathBuf::from(&arg[2..])),
+            _ if arg.starts_with("-D") => macros.push(MacroOption::Define(arg[2..].to_string())),
+            _ if arg.starts_with("-U") => macros.push(MacroOption::Undefine(arg[2..].to_string())),
+            _ if arg.starts_with('-') && arg != "-" => {
+                usage_error(&format!("unknown option '{}'", arg))
+            }
+            _ => {
+                if input.is_some() {
+                    usage_error("multiple input files are not supported");
+                }
+                input = Some(arg);
+            }
+        }
+    }
+    // the program of --run is given on the command line
+    let input = match (input, &run) {
+        (Some(_), Some(_)) => usage_error("an input file cannot be used with '--run'"),
+        (Some(input), None) => input,
+        (None, Some(_)) => "<command line>".to_string(),
+        (None, None) => usage_error("no input file"),
+    };
+    Options {
+        input,
+        output,
+        dump,
+        assembly_only,
+        run,
+        include_paths,
+        macros,
+    }
+}
+
+fn read_input(input: &str) -> io::Result<String> {
+    if input == "-" {
+        let mut src = String::new();
+        io::stdin().read_to_string(&mut src)?;
+        Ok(src)
+    } else {
+        fs::read_to_string(input)
+    }
+}
+
+fn write_output(output: &str, contents: &str) -> io::Result<()> {
+    if output == "-" {
+        print!("{}", contents);
+        Ok(())
+    } else {
+        fs::write(output, contents)
+    }
+}
+
+// Default output of -S: foo.c -> foo.s, stdin -> stdout
+fn assembly_path(input: &str) -> String {
+    if input == "-" {
+        return "-".to_string();
+    }
+    let name = Path::new(input).file_name().unwrap_or_default();
+    Path::new(name)
+        .with_extension("s")
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Assemble and link with the system `cc`
+fn link(assembly: &str, output: &str) -> io::Result<bool> {
+    let tmp: PathBuf = env::temp_dir().join(format!("r9cc-{}.s", process::id()));
+    fs::write(&tmp, assembly)?;
+    let status = Command::new("cc").arg("-o").arg(output).arg(&tmp).status();
+    let _ = fs::remove_file(&tmp);
+    Ok(status?.success())
+}
+
+fn report_errors(sources: &SourceMap, errors: &[CompileError]) {
+    for error in errors {
+        eprintln!("{}", sources.render(error));
+    }
+    match errors.len() {
+        1 => eprintln!("1 error generated."),
+        n => eprintln!("{} errors generated.", n),
+    }
+}
+
+// Preprocessor with the include paths and the macros of the options
+fn new_preprocessor(options: &Options) -> Preprocessor {
+    let mut preprocessor = Preprocessor::new(options.include_paths.clone());
+    for option in &options.macros {
+        match option {
+            MacroOption::Define(definition) => preprocessor.define(definition),
+            MacroOption::Undefine(name) => preprocessor.undefine(name),
+        }
+    }
+    preprocessor
+}
+
+// Interpret the program of --run. Statements, which make the parser expect a
+// declaration, are the body of `main`, like --run "int a = 3; return a;"
+fn interpret(options: &Options, program: &str) -> (Preprocessor, Result<i64, Vec<CompileError>>) {
+    let mut preprocessor = new_preprocessor(options);
+    let result = Interpreter::run_with(&mut preprocessor, &options.input, program);
+    match &result {
+        Err(errors) if errors[0].error_type == CompileErrorType::Parsing(ParseError::NotType) => {
+            let mut preprocessor = new_preprocessor(options);
+            let body = format!("int main() {{ {}\n}}", program);
+            let result = Interpreter::run_with(&mut preprocessor, &options.input, &body);
+            (preprocessor, result)
+        }
+        _ => (preprocessor, result),
+    }
+}
+
 fn main() {
-    let arg = env::args().nth(1).unwrap_or_else(|| {
-        eprintln!("usage  : ./r9cc \"<code>\"");
-        eprintln!("example: ./r9cc \"4+3+10-9\"");
+    let options = parse_args(env::args().skip(1));
+    if let Some(program) = &options.run {
+        let (preprocessor, result) = interpret(&options, program);
+        match result {
+            Ok(value) => process::exit(value as i32),
+            Err(errors) => {
+                report_errors(preprocessor.sources(), &errors);
+                process::exit(1);
+            }
+        }
+    }
+    let mut preprocessor = new_preprocessor(&options);
+    let src = read_input(&options.input).unwrap_or_else(|err| {
+        eprintln!("r9cc: cannot read {}: {}", options.input, err);
         process::exit(1);
     });
-    let out = r9cc::compile(&arg);
-    match out {
-        Ok(assemblys) => {
-            println!("{}", assemblys.join("\n"));
-        }
-        Err(err) => {
-            eprintln!("Error: (input) {}", arg);
-            eprintln!("{:#?}", err);
+    let filename = if options.input == "-" {
+        "<stdin>"
+    } else {
+        &options.input
+    };
+    if let Some(kind) = &options.dump {
+        let text = match kind {
+            Dump::Preprocessed => preprocessor
+                .run(filename, &src)
+                .map(|tokens| preprocessor.sources().preprocessed(&tokens)),
+            Dump::Tokens => dump::tokens(&mut preprocessor, filename, &src),
+            Dump::Ast(format) => dump::ast(&mut preprocessor, filename, &src, *format),
+            Dump::Dot => dump::dot(&mut preprocessor, filename, &src),
+        };
+        let text = match text {
+            Ok(text) => text,
+            Err(errors) => {
+                report_errors(preprocessor.sources(), &errors);
+                process::exit(1);
+            }
+        };
+        let output = options.output.unwrap_or_else(|| "-".to_string());
+        if let Err(err) = write_output(&output, &text) {
+            eprintln!("r9cc: cannot write {}: {}", output, err);
+            process::exit(1);
+        }
+        return;
+    }
+    let assembly = match r9cc::compile_with(&mut preprocessor, filename, &src) {
+        Ok(assemblys) => assemblys.join("\n") + "\n",
+        Err(errors) => {
+            report_errors(preprocessor.sources(), &errors);
+            process::exit(1);
+        }
+    };
+    if options.assembly_only {
+        let output = options
+            .output
+            .unwrap_or_else(|| assembly_path(&options.input));
+        if let Err(err) = write_output(&output, &assembly) {
+            eprintln!("r9cc: cannot write {}: {}", output, err);
+            process::exit(1);
+        }
+    } else {
+        let output = options.output.unwrap_or_else(|| "a.out".to_string());
+        match link(&assembly, &output) {
+            Ok(true) => {}
+            Ok(false) => process::exit(1),
+            Err(err) => {
+                eprintln!("r9cc: cannot run cc: {}", err);
+                process::exit(1);
+            }
         }
     }
 }
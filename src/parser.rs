@@ -1,114 +1,1050 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
-use crate::errors::{CompileError, CompileErrorType, ParseError};
-use crate::tokenizer::{Separator, TokenKind, Tokens};
+use crate::errors::{
+    CompileError, CompileErrorType, ParseError, PreprocessError, TokenizeError, TypeError,
+};
+use crate::preprocessor::Preprocessor;
+use crate::tokenizer::{unescape, RawTokens, Separator, Token, TokenKind, Tokens};
+use crate::types::{self, Type};
 
-#[derive(Debug)]
+// Preprocess, parse and type the main file `name`, whose contents are `src`
+pub fn parse(
+    preprocessor: &mut Preprocessor,
+    name: &str,
+    src: &str,
+) -> Result<Program, Vec<CompileError>> {
+    let mut tokens = RawTokens::new(preprocessor.run(name, src)?).peekable();
+    let mut parser = Parser::new(src);
+    let mut program = parser.program(&mut tokens)?;
+    let errors: Vec<CompileError> = program
+        .functions
+        .iter_mut()
+        .flat_map(|f| f.body.iter_mut())
+        .filter_map(|node| types::add_type(node).err())
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(program)
+}
+
+// Evaluate the tokens of '#if', whose end is `eof`
+pub fn eval_condition(tokens: Vec<Token>, eof: Range<usize>) -> Result<i64, CompileError> {
+    let mut tokens = RawTokens::new(tokens).peekable();
+    let mut parser = Parser::new("");
+    parser.eof = eof;
+    let node = parser.assign(&mut tokens)?;
+    if let Some(token) = tokens.next() {
+        return Err(parser.error(ParseError::CannotParse, token.span));
+    }
+    eval_intmax(&node).map(|(value, _)| value)
+}
+
+fn is_typename(tokens: &mut Tokens) -> bool {
+    matches!(
+        tokens.peek().map(|token| token.kind),
+        Some(TokenKind::Int | TokenKind::Char)
+    )
+}
+
+// Eat the next token if it is `kind`, and return its span
+fn consume_span(tokens: &mut Tokens, kind: TokenKind) -> Option<Range<usize>> {
+    if tokens.peek().map(|token| token.kind) == Some(kind) {
+        tokens.next().map(|token| token.span)
+    } else {
+        None
+    }
+}
+
+// Eat the next token if it is `kind`
+fn consume(tokens: &mut Tokens, kind: TokenKind) -> bool {
+    consume_span(tokens, kind).is_some()
+}
+
+// Skip the rest of a broken statement, so that parsing can go on after an error.
+// The next ";" is eaten, and a "}" closing the enclosing block is left.
+fn synchronize(tokens: &mut Tokens) {
+    let mut depth = 0; // nesting of "{" skipped here
+    while let Some(token) = tokens.peek() {
+        match token.kind {
+            TokenKind::Sep(Separator::SemiColon) if depth == 0 => {
+                tokens.next();
+                return;
+            }
+            TokenKind::Sep(Separator::CurlyBracketL) => depth += 1,
+            TokenKind::Sep(Separator::CurlyBracketR) => {
+                if depth == 0 {
+                    return;
+                }
+                depth -= 1;
+                if depth == 0 {
+                    tokens.next();
+                    return;
+                }
+            }
+            _ => {}
+        }
+        tokens.next();
+    }
+}
+
+// Evaluate a constant expression
+fn eval(node: &Node) -> Option<i64> {
+    let lhs = node.lhs.as_deref().and_then(eval);
+    let rhs = node.rhs.as_deref().and_then(eval);
+    match node.kind {
+        NodeKind::Number(num) => Some(num),
+        NodeKind::Add => lhs?.checked_add(rhs?),
+        NodeKind::Sub => lhs?.checked_sub(rhs?),
+        NodeKind::Mul => lhs?.checked_mul(rhs?),
+        NodeKind::Div => lhs?.checked_div(rhs?),
+        NodeKind::Mod => lhs?.checked_rem(rhs?),
+        NodeKind::BitAnd => Some(lhs? & rhs?),
+        NodeKind::BitOr => Some(lhs? | rhs?),
+        NodeKind::BitXor => Some(lhs? ^ rhs?),
+        NodeKind::Shl => lhs?.checked_shl(u32::try_from(rhs?).ok()?),
+        NodeKind::Shr => lhs?.checked_shr(u32::try_from(rhs?).ok()?),
+        NodeKind::LogAnd => Some((lhs? != 0 && rhs? != 0) as i64),
+        NodeKind::LogOr => Some((lhs? != 0 || rhs? != 0) as i64),
+        NodeKind::Not => Some((lhs? == 0) as i64),
+        NodeKind::BitNot => Some(!lhs?),
+        NodeKind::Cond => {
+            if eval(node.cond.as_deref()?)? != 0 {
+                eval(node.then.as_deref()?)
+            } else {
+                eval(node.els.as_deref()?)
+            }
+        }
+        NodeKind::Eq => Some((lhs? == rhs?) as i64),
+        NodeKind::NotEq => Some((lhs? != rhs?) as i64),
+        NodeKind::Less => Some((lhs? < rhs?) as i64),
+        NodeKind::LessEq => Some((lhs? <= rhs?) as i64),
+        _ => None,
+    }
+}
+
+// Evaluate the expression of '#if', where every integer is intmax_t, or uintmax_t
+// if it is unsigned, with the usual arithmetic conversions. Returns the value and
+// whether it is unsigned.
+fn eval_intmax(node: &Node) -> Result<(i64, bool), CompileError> {
+    let error = |error| CompileError {
+        error_type: CompileErrorType::Preprocessing(error),
+        pos: Some(node.span.clone()),
+    };
+    let operand = |operand: &Option<Box<Node>>| match operand {
+        Some(operand) => eval_intmax(operand),
+        None => Err(error(PreprocessError::InvalidCondition)),
+    };
+    let lhs = || operand(&node.lhs);
+    let rhs = || operand(&node.rhs);
+    let truth = |value: bool| Ok((value as i64, false));
+    let overflow = || error(PreprocessError::ConditionOverflow);
+    // operands of an arithmetic operator, and whether it is done in uintmax_t
+    let operands = || {
+        let ((lhs, lhs_unsigned), (rhs, rhs_unsigned)) = (lhs()?, rhs()?);
+        Ok((lhs, rhs, lhs_unsigned || rhs_unsigned))
+    };
+    match node.kind {
+        NodeKind::Number(num) => Ok((num, node.ty.as_ref().is_some_and(Type::is_unsigned))),
+        NodeKind::Add
+        | NodeKind::Sub
+        | NodeKind::Mul
+        | NodeKind::BitAnd
+        | NodeKind::BitOr
+        | NodeKind::BitXor => {
+            let (lhs, rhs, unsigned) = operands()?;
+            let value = match node.kind {
+                NodeKind::Add if unsigned => lhs.wrapping_add(rhs),
+                NodeKind::Sub if unsigned => lhs.wrapping_sub(rhs),
+                NodeKind::Mul if unsigned => lhs.wrapping_mul(rhs),
+                NodeKind::Add => lhs.checked_add(rhs).ok_or_else(overflow)?,
+                NodeKind::Sub => lhs.checked_sub(rhs).ok_or_else(overflow)?,
+                NodeKind::Mul => lhs.checked_mul(rhs).ok_or_else(overflow)?,
+                NodeKind::BitAnd => lhs & rhs,
+                NodeKind::BitOr => lhs | rhs,
+                _ => lhs ^ rhs,
+            };
+            Ok((value, unsigned))
+        }
+        NodeKind::Div | NodeKind::Mod => {
+            let (lhs, rhs, unsigned) = operands()?;
+            if rhs == 0 {
+                return Err(error(PreprocessError::DivisionByZero));
+            }
+            let value = match (node.kind, unsigned) {
+                (NodeKind::Div, true) => ((lhs as u64) / (rhs as u64)) as i64,
+                (NodeKind::Div, false) => lhs.checked_div(rhs).ok_or_else(overflow)?,
+                (_, true) => ((lhs as u64) % (rhs as u64)) as i64,
+                (_, false) => lhs.checked_rem(rhs).ok_or_else(overflow)?,
+            };
+            Ok((value, unsigned))
+        }
+        // the result has the type of the left operand
+        NodeKind::Shl | NodeKind::Shr => {
+            let ((lhs, unsigned), (rhs, _)) = (lhs()?, rhs()?);
+            let rhs = u32::try_from(rhs)
+                .ok()
+                .filter(|&rhs| rhs < 64)
+                .ok_or_else(overflow)?;
+            let value = match (node.kind, unsigned) {
+                (NodeKind::Shl, _) => lhs << rhs,
+                (_, true) => ((lhs as u64) >> rhs) as i64,
+                (_, false) => lhs >> rhs,
+            };
+            Ok((value, unsigned))
+        }
+        NodeKind::Eq | NodeKind::NotEq | NodeKind::Less | NodeKind::LessEq => {
+            let (lhs, rhs, unsigned) = operands()?;
+            let ordering = if unsigned {
+                (lhs as u64).cmp(&(rhs as u64))
+            } else {
+                lhs.cmp(&rhs)
+            };
+            truth(match node.kind {
+                NodeKind::Eq => ordering.is_eq(),
+                NodeKind::NotEq => ordering.is_ne(),
+                NodeKind::Less => ordering.is_lt(),
+                _ => ordering.is_le(),
+            })
+        }
+        // the right operand is not evaluated when the left decides the result
+        NodeKind::LogAnd => truth(lhs()?.0 != 0 && rhs()?.0 != 0),
+        NodeKind::LogOr => truth(lhs()?.0 != 0 || rhs()?.0 != 0),
+        NodeKind::Not => truth(lhs()?.0 == 0),
+        NodeKind::BitNot => lhs().map(|(value, unsigned)| (!value, unsigned)),
+        // only the chosen operand is evaluated, but the other may make the result unsigned
+        NodeKind::Cond => {
+            let unsigned = |node| operand(node).is_ok_and(|(_, unsigned)| unsigned);
+            let value = if operand(&node.cond)?.0 != 0 {
+                operand(&node.then)?.0
+            } else {
+                operand(&node.els)?.0
+            };
+            Ok((value, unsigned(&node.then) || unsigned(&node.els)))
+        }
+        _ => Err(error(PreprocessError::InvalidCondition)),
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct LocalVar {
-    offset: usize,
+    pub offset: usize,
+    pub ty: Type,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum NodeKind {
     Var(usize), // variable (converted ident) // usize is offset
+    GlobalVar,  // global variable, named by `Node::name`
     Number(i64),
     Add,
     Sub,
     Mul,
     Div,
+    Mod,    // '%'
+    BitAnd, // binary '&'
+    BitOr,  // '|'
+    BitXor, // '^'
+    Shl,    // '<<'
+    Shr,    // '>>'
+    LogAnd, // '&&'
+    LogOr,  // '||'
+    Not,    // '!'
+    BitNot, // '~'
     Eq,     // '=='
     NotEq,  // '!='
     Less,   // '<'
     LessEq, // '<='
     Assign, // '='
-    Return, // 'return'
+    // compound assignment "a op= b", "++a" and "--a", whose lhs is the operation "a op b"
+    AssignOp,
+    PostAssignOp, // "a++" and "a--", which are valued before the update
+    Cond,         // "cond ? then : els"
+    Comma,        // ','
+    Return,       // 'return'
+    If,           // 'if'
+    While,        // 'while'
+    For,          // 'for'
+    Break,        // 'break'
+    Continue,     // 'continue'
+    Block,        // '{' stmt* '}'
+    FuncCall,     // function call
+    Addr,         // unary '&'
+    Deref,        // unary '*'
+    Sizeof,       // 'sizeof', replaced with a number by `types::add_type`
 }
 
 #[derive(Debug)]
 pub struct Node {
     pub kind: NodeKind,
+    pub span: Range<usize>, // source range which the node covers
     pub lhs: Option<Box<Node>>,
     pub rhs: Option<Box<Node>>,
+    // "if", "while" and "for" statement
+    pub cond: Option<Box<Node>>,
+    pub then: Option<Box<Node>>,
+    pub els: Option<Box<Node>>,
+    pub init: Option<Box<Node>>,
+    pub inc: Option<Box<Node>>,
+    // block
+    pub body: Vec<Node>,
+    // function call
+    pub name: Option<String>, // called function or global variable
+    pub args: Vec<Node>,
+    pub ty: Option<Type>, // type of the expression, set by `types::add_type`
 }
 
 impl Node {
-    fn new(kind: NodeKind, lhs: Option<Node>, rhs: Option<Node>) -> Self {
+    fn new(kind: NodeKind, lhs: Option<Node>, rhs: Option<Node>, span: Range<usize>) -> Self {
         Node {
             kind,
+            span,
             lhs: lhs.map(Box::new),
             rhs: rhs.map(Box::new),
+            cond: None,
+            then: None,
+            els: None,
+            init: None,
+            inc: None,
+            body: vec![],
+            name: None,
+            args: vec![],
+            ty: None,
         }
     }
+
+    // binary operator, which spans from the start of lhs to the end of rhs
+    fn binary(kind: NodeKind, lhs: Node, rhs: Node) -> Self {
+        let span = lhs.span.start..rhs.span.end;
+        Node::new(kind, Some(lhs), Some(rhs), span)
+    }
+
+    fn with_type(mut self, ty: Type) -> Self {
+        self.ty = Some(ty);
+        self
+    }
 }
 
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub return_ty: Type,
+    pub params: Vec<LocalVar>,
+    pub body: Vec<Node>,
+    pub stack_size: usize, // size of the frame for local variables
+}
+
+#[derive(Debug)]
+pub struct GlobalVar {
+    pub name: String,
+    pub ty: Type,
+    pub init: Option<i64>, // initial value, or zero-filled
+}
+
+// String literal, which is an anonymous read-only array
+#[derive(Debug)]
+pub struct StrLiteral {
+    pub name: String,
+    pub data: Vec<u8>, // null-terminated
+}
+
+#[derive(Debug)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub globals: Vec<GlobalVar>,
+    pub strings: Vec<StrLiteral>,
+}
+
+// Arguments are passed in these registers, so this is the maximum number of arguments
+pub const MAX_ARGS: usize = 6;
+
 #[derive(Debug)]
 pub struct Parser {
-    locals: HashMap<String, LocalVar>,
+    locals: Vec<LocalVar>,               // every local variable in the program
+    scopes: Vec<HashMap<String, usize>>, // name -> index of `locals`, innermost last
+    loop_depth: usize,                   // number of loops enclosing the current statement
+    globals: Vec<GlobalVar>,
+    strings: Vec<StrLiteral>,
+    return_types: HashMap<String, Type>, // functions declared or defined so far
+    eof: Range<usize>,                   // empty span at the end of the input
+    errors: Vec<CompileError>,           // errors recovered from so far
 }
 
 impl Parser {
-    pub fn new() -> Parser {
+    pub fn new(src: &str) -> Parser {
         Parser {
-            locals: HashMap::new(),
+            locals: vec![],
+            scopes: vec![HashMap::new()],
+            loop_depth: 0,
+            globals: vec![],
+            strings: vec![],
+            return_types: HashMap::new(),
+            eof: src.len()..src.len(),
+            errors: vec![],
         }
     }
 
-    fn offset(&self) -> usize {
-        (self.locals.len() + 1) * 8
+    // Span of the next token, or the end of the input
+    fn peek_span(&self, tokens: &mut Tokens) -> Range<usize> {
+        tokens
+            .peek()
+            .map_or_else(|| self.eof.clone(), |token| token.span.clone())
+    }
+
+    fn error(&self, error: ParseError, span: Range<usize>) -> CompileError {
+        CompileError {
+            error_type: CompileErrorType::Parsing(error),
+            pos: Some(span),
+        }
+    }
+
+    // Eat the next token, or raise `error` if it is not `kind`
+    fn expect(
+        &self,
+        tokens: &mut Tokens,
+        kind: TokenKind,
+        error: ParseError,
+    ) -> Result<Range<usize>, CompileError> {
+        match consume_span(tokens, kind) {
+            Some(span) => Ok(span),
+            None => Err(self.error(error, self.peek_span(tokens))),
+        }
+    }
+
+    // Search a variable from the innermost scope
+    fn find_var(&self, name: &str) -> Option<&LocalVar> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .map(|&index| &self.locals[index])
+    }
+
+    // Create a variable in the innermost scope
+    fn new_var(&mut self, name: &str, ty: Type) -> &LocalVar {
+        let offset = self.offset(&ty);
+        self.locals.push(LocalVar { offset, ty });
+        let index = self.locals.len() - 1;
+        self.scopes
+            .last_mut()
+            .expect("no scope")
+            .insert(name.to_string(), index);
+        &self.locals[index]
+    }
+
+    // Variables are placed below rbp, so a new variable is put after the used area
+    fn offset(&self, ty: &Type) -> usize {
+        let used = self.locals.iter().map(|var| var.offset).max().unwrap_or(0);
+        (used + ty.size()).next_multiple_of(ty.align())
     }
 
-    pub fn program(&mut self, tokens: &mut Tokens) -> Result<Vec<Node>, CompileError> {
-        let mut code = vec![];
+    // Bytes used by all local variables, rounded up so that rsp stays 16-byte aligned
+    fn stack_size(&self) -> usize {
+        let size = self.locals.iter().map(|var| var.offset).max().unwrap_or(0);
+        size.next_multiple_of(16)
+    }
+
+    // String literals are referred to by their unique label
+    fn new_string_literal(&mut self, data: Vec<u8>, span: Range<usize>) -> Node {
+        let name = format!(".L.str.{}", self.strings.len());
+        let ty = Type::array_of(Type::Char, data.len());
+        self.strings.push(StrLiteral {
+            name: name.clone(),
+            data,
+        });
+        let mut node = Node::new(NodeKind::GlobalVar, None, None, span).with_type(ty);
+        node.name = Some(name);
+        node
+    }
+
+    fn find_global(&self, name: &str) -> Option<&GlobalVar> {
+        self.globals.iter().find(|var| var.name == name)
+    }
+
+    // (function | global-variables)*
+    // Every syntax error in the input is reported
+    pub fn program(&mut self, tokens: &mut Tokens) -> Result<Program, Vec<CompileError>> {
+        let mut functions = vec![];
         while tokens.peek().is_some() {
-            code.push(self.stmt(tokens)?);
+            if let Err(error) = self.top_level(tokens, &mut functions) {
+                self.errors.push(error);
+                synchronize(tokens);
+                // a stray "}" is skipped, since there is no block to close
+                consume(tokens, TokenKind::Sep(Separator::CurlyBracketR));
+            }
         }
-        Ok(code)
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+        Ok(Program {
+            functions,
+            globals: std::mem::take(&mut self.globals),
+            strings: std::mem::take(&mut self.strings),
+        })
     }
 
-    fn stmt(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
-        let node;
-        if let Some(token) = tokens.peek() {
-            if token.kind == TokenKind::Return {
-                tokens.next();
-                node = Node::new(NodeKind::Return, Some(self.expr(tokens)?), None);
-            } else {
-                node = self.expr(tokens)?;
+    // declspec declarator (function | global-variables)
+    fn top_level(
+        &mut self,
+        tokens: &mut Tokens,
+        functions: &mut Vec<Function>,
+    ) -> Result<(), CompileError> {
+        let base = self.declspec(tokens)?;
+        let span = self.peek_span(tokens);
+        let (ty, name) = self.declarator(tokens, base.clone())?;
+        if consume(tokens, TokenKind::Sep(Separator::RoundBracketL)) {
+            if self.find_global(&name).is_some() {
+                return Err(self.error(ParseError::RedefinedKind, span));
+            }
+            // a declaration without a body only tells the return type
+            if let Some(function) = self.function(tokens, name, ty)? {
+                if functions
+                    .iter()
+                    .any(|defined| defined.name == function.name)
+                {
+                    return Err(self.error(ParseError::RedefinedFunc, span));
+                }
+                functions.push(function);
             }
         } else {
-            return Err(CompileError {
-                error_type: CompileErrorType::Parsing(ParseError::Empty),
-                pos: None,
-            });
+            self.global_variables(tokens, base, (ty, name), span)?;
         }
+        Ok(())
+    }
+
+    // The first declarator is already read by `top_level`
+    // (declarator ("=" expr)?) ("," declarator ("=" expr)?)* ";"
+    fn global_variables(
+        &mut self,
+        tokens: &mut Tokens,
+        base: Type,
+        first: (Type, String),
+        mut span: Range<usize>,
+    ) -> Result<(), CompileError> {
+        let (mut ty, mut name) = first;
+        loop {
+            if self.find_global(&name).is_some() {
+                return Err(self.error(ParseError::RedefinedVar, span));
+            }
+            if self.return_types.contains_key(&name) {
+                return Err(self.error(ParseError::RedefinedKind, span));
+            }
+            let mut init = None;
+            if consume(tokens, TokenKind::Assign) {
+                if let Type::Array(..) = ty {
+                    return Err(CompileError {
+                        error_type: CompileErrorType::Typing(TypeError::NotLValue),
+                        pos: Some(span),
+                    });
+                }
+                init = Some(self.const_expr(tokens)?);
+            }
+            self.globals.push(GlobalVar { name, ty, init });
+            if !consume(tokens, TokenKind::Sep(Separator::Comma)) {
+                break;
+            }
+            span = self.peek_span(tokens);
+            (ty, name) = self.declarator(tokens, base.clone())?;
+        }
+        self.expect(
+            tokens,
+            TokenKind::Sep(Separator::SemiColon),
+            ParseError::NeedSemiColon,
+        )?;
+        Ok(())
+    }
+
+    // expression which is evaluated at compile time
+    fn const_expr(&mut self, tokens: &mut Tokens) -> Result<i64, CompileError> {
+        let node = self.assign(tokens)?;
+        eval(&node).ok_or_else(|| self.error(ParseError::NotConstant, node.span))
+    }
+
+    // The name, its return type and "(" are already read by `top_level`
+    // (param ("," param)*)? ")" ("{" stmt* "}" | ";")
+    // param = declspec "*"* ident? type-suffix, where ident is only optional in declarations
+    fn function(
+        &mut self,
+        tokens: &mut Tokens,
+        name: String,
+        return_ty: Type,
+    ) -> Result<Option<Function>, CompileError> {
+        // each function has its own local variables
+        self.locals = vec![];
+        self.scopes = vec![HashMap::new()];
+        // calls in the body can be typed, including recursive ones
+        self.return_types.insert(name.clone(), return_ty.clone());
+
+        let mut params = vec![];
+        if !consume(tokens, TokenKind::Sep(Separator::RoundBracketR)) {
+            loop {
+                if params.len() == MAX_ARGS {
+                    return Err(self.error(ParseError::TooManyArgs, self.peek_span(tokens)));
+                }
+                let base = self.declspec(tokens)?;
+                let mut ty = base;
+                while consume(tokens, TokenKind::Mul) {
+                    ty = Type::pointer_to(ty);
+                }
+                let span = self.peek_span(tokens);
+                let param = match tokens.peek() {
+                    Some(token) if token.kind == TokenKind::Ident => Some(self.ident(tokens)?),
+                    _ => None,
+                };
+                let ty = self.type_suffix(tokens, ty)?;
+                params.push((ty.decay(), param, span));
+                if !consume(tokens, TokenKind::Sep(Separator::Comma)) {
+                    break;
+                }
+            }
+            self.expect(
+                tokens,
+                TokenKind::Sep(Separator::RoundBracketR),
+                ParseError::NotFoundRoundBracketR,
+            )?;
+        }
+        if consume(tokens, TokenKind::Sep(Separator::SemiColon)) {
+            return Ok(None);
+        }
+        let params = params
+            .into_iter()
+            .map(|(ty, param, span)| match param {
+                Some(param) => Ok(self.new_var(&param, ty).clone()),
+                None => Err(self.error(ParseError::NotIdent, span)),
+            })
+            .collect::<Result<Vec<LocalVar>, CompileError>>()?;
+        self.expect(
+            tokens,
+            TokenKind::Sep(Separator::CurlyBracketL),
+            ParseError::NotFoundCurlyBracketL,
+        )?;
+        let (body, _) = self.block_body(tokens)?;
+        Ok(Some(Function {
+            name,
+            return_ty,
+            params,
+            body,
+            stack_size: self.stack_size(),
+        }))
+    }
+
+    // "int" | "char"
+    fn declspec(&mut self, tokens: &mut Tokens) -> Result<Type, CompileError> {
+        if consume(tokens, TokenKind::Char) {
+            return Ok(Type::Char);
+        }
+        self.expect(tokens, TokenKind::Int, ParseError::NotType)?;
+        Ok(Type::Int)
+    }
+
+    // "*"* ident type-suffix
+    fn declarator(
+        &mut self,
+        tokens: &mut Tokens,
+        mut ty: Type,
+    ) -> Result<(Type, String), CompileError> {
+        while consume(tokens, TokenKind::Mul) {
+            ty = Type::pointer_to(ty);
+        }
+        let name = self.ident(tokens)?;
+        Ok((self.type_suffix(tokens, ty)?, name))
+    }
+
+    // "*"* type-suffix
+    fn abstract_declarator(
+        &mut self,
+        tokens: &mut Tokens,
+        mut ty: Type,
+    ) -> Result<Type, CompileError> {
+        while consume(tokens, TokenKind::Mul) {
+            ty = Type::pointer_to(ty);
+        }
+        self.type_suffix(tokens, ty)
+    }
+
+    // ("[" num "]" type-suffix)?
+    fn type_suffix(&mut self, tokens: &mut Tokens, ty: Type) -> Result<Type, CompileError> {
+        if !consume(tokens, TokenKind::Sep(Separator::SquareBracketL)) {
+            return Ok(ty);
+        }
+        let span = self.peek_span(tokens);
+        let len = match tokens.peek().map(|token| token.kind) {
+            Some(TokenKind::Number(num, _)) if num >= 0 => num as usize,
+            _ => return Err(self.error(ParseError::NotNumber, span)),
+        };
+        tokens.next();
+        self.expect(
+            tokens,
+            TokenKind::Sep(Separator::SquareBracketR),
+            ParseError::NotFoundSquareBracketR,
+        )?;
+        let ty = self.type_suffix(tokens, ty)?;
+        // an object is addressed with isize offsets
+        match ty.size().checked_mul(len) {
+            Some(size) if size <= isize::MAX as usize => Ok(Type::array_of(ty, len)),
+            _ => Err(self.error(ParseError::ArrayTooLarge, span)),
+        }
+    }
+
+    // declspec (declarator ("=" expr)? ("," declarator ("=" expr)?)*)? ";"
+    fn declaration(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let start = self.peek_span(tokens);
+        let base = self.declspec(tokens)?;
+        let mut body = vec![];
+        if let Some(end) = consume_span(tokens, TokenKind::Sep(Separator::SemiColon)) {
+            return Ok(Node::new(NodeKind::Block, None, None, start.start..end.end));
+        }
+        loop {
+            let span = self.peek_span(tokens);
+            let (ty, name) = self.declarator(tokens, base.clone())?;
+            let scope = self.scopes.last().expect("no scope");
+            if scope.contains_key(&name) {
+                return Err(self.error(ParseError::RedefinedVar, span));
+            }
+            let var = self.new_var(&name, ty);
+            if consume(tokens, TokenKind::Assign) {
+                let lhs = Node::new(NodeKind::Var(var.offset), None, None, span)
+                    .with_type(var.ty.clone());
+                let rhs = self.assign(tokens)?;
+                body.push(Node::binary(NodeKind::Assign, lhs, rhs));
+            }
+            if !consume(tokens, TokenKind::Sep(Separator::Comma)) {
+                break;
+            }
+        }
+        let end = self.expect(
+            tokens,
+            TokenKind::Sep(Separator::SemiColon),
+            ParseError::NeedSemiColon,
+        )?;
+        let mut node = Node::new(NodeKind::Block, None, None, start.start..end.end);
+        node.body = body;
+        Ok(node)
+    }
+
+    fn ident(&mut self, tokens: &mut Tokens) -> Result<String, CompileError> {
+        match tokens.peek() {
+            Some(token) if token.kind == TokenKind::Ident => {
+                let name = token.text.to_string();
+                tokens.next();
+                Ok(name)
+            }
+            _ => Err(self.error(ParseError::NotIdent, self.peek_span(tokens))),
+        }
+    }
+
+    fn stmt(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let mut node;
         if let Some(token) = tokens.peek() {
-            if token.kind != TokenKind::Sep(Separator::SemiColon) {
-                return Err(CompileError {
-                    error_type: CompileErrorType::Parsing(ParseError::NeedSemiColon),
-                    pos: Some(token.span.clone()),
-                });
-            } else {
-                tokens.next(); // eat ';'
+            let span = token.span.clone();
+            match token.kind {
+                TokenKind::Return => {
+                    tokens.next();
+                    let expr = self.expr(tokens)?;
+                    let span = span.start..expr.span.end;
+                    node = Node::new(NodeKind::Return, Some(expr), None, span);
+                }
+                TokenKind::If => {
+                    tokens.next();
+                    return self.if_stmt(tokens, span);
+                }
+                TokenKind::While => {
+                    tokens.next();
+                    return self.while_stmt(tokens, span);
+                }
+                TokenKind::For => {
+                    tokens.next();
+                    return self.for_stmt(tokens, span);
+                }
+                TokenKind::Sep(Separator::CurlyBracketL) => {
+                    tokens.next();
+                    return self.compound_stmt(tokens, span);
+                }
+                // the null statement is an empty block
+                TokenKind::Sep(Separator::SemiColon) => {
+                    tokens.next();
+                    return Ok(Node::new(NodeKind::Block, None, None, span));
+                }
+                TokenKind::Int | TokenKind::Char => {
+                    return self.declaration(tokens);
+                }
+                TokenKind::Break | TokenKind::Continue => {
+                    if self.loop_depth == 0 {
+                        return Err(self.error(ParseError::NotInLoop, span));
+                    }
+                    let kind = if token.kind == TokenKind::Break {
+                        NodeKind::Break
+                    } else {
+                        NodeKind::Continue
+                    };
+                    tokens.next();
+                    node = Node::new(kind, None, None, span);
+                }
+                _ => {
+                    node = self.expr(tokens)?;
+                }
             }
         } else {
-            return Err(CompileError {
-                error_type: CompileErrorType::Parsing(ParseError::NeedSemiColon),
-                pos: None,
-            });
+            return Err(self.error(ParseError::Empty, self.eof.clone()));
+        }
+        let end = self.expect(
+            tokens,
+            TokenKind::Sep(Separator::SemiColon),
+            ParseError::NeedSemiColon,
+        )?;
+        // statements include ';', while expressions keep their own range
+        if matches!(
+            node.kind,
+            NodeKind::Return | NodeKind::Break | NodeKind::Continue
+        ) {
+            node.span.end = end.end;
         }
         Ok(node)
     }
 
+    // "if" "(" expr ")" stmt ("else" stmt)?
+    fn if_stmt(&mut self, tokens: &mut Tokens, span: Range<usize>) -> Result<Node, CompileError> {
+        let mut node = Node::new(NodeKind::If, None, None, span);
+        self.expect(
+            tokens,
+            TokenKind::Sep(Separator::RoundBracketL),
+            ParseError::NotFoundRoundBracketL,
+        )?;
+        node.cond = Some(Box::new(self.expr(tokens)?));
+        self.expect(
+            tokens,
+            TokenKind::Sep(Separator::RoundBracketR),
+            ParseError::NotFoundRoundBracketR,
+        )?;
+        let then = self.stmt(tokens)?;
+        node.span.end = then.span.end;
+        node.then = Some(Box::new(then));
+        if consume(tokens, TokenKind::Else) {
+            let els = self.stmt(tokens)?;
+            node.span.end = els.span.end;
+            node.els = Some(Box::new(els));
+        }
+        Ok(node)
+    }
+
+    // "{" stmt* "}"
+    fn compound_stmt(
+        &mut self,
+        tokens: &mut Tokens,
+        span: Range<usize>,
+    ) -> Result<Node, CompileError> {
+        self.scopes.push(HashMap::new());
+        let body = self.block_body(tokens);
+        self.scopes.pop();
+        let (body, end) = body?;
+        let mut node = Node::new(NodeKind::Block, None, None, span.start..end.end);
+        node.body = body;
+        Ok(node)
+    }
+
+    // statements until "}", and the span of the "}"
+    fn block_body(
+        &mut self,
+        tokens: &mut Tokens,
+    ) -> Result<(Vec<Node>, Range<usize>), CompileError> {
+        let mut body = vec![];
+        loop {
+            match tokens.peek() {
+                Some(token) if token.kind == TokenKind::Sep(Separator::CurlyBracketR) => {
+                    let span = token.span.clone();
+                    tokens.next();
+                    return Ok((body, span));
+                }
+                Some(_) => match self.stmt(tokens) {
+                    Ok(stmt) => body.push(stmt),
+                    Err(error) => {
+                        self.errors.push(error);
+                        synchronize(tokens);
+                    }
+                },
+                None => return Err(self.error(ParseError::NotFoundCurlyBracketR, self.eof.clone())),
+            }
+        }
+    }
+
+    // "while" "(" expr ")" stmt
+    fn while_stmt(
+        &mut self,
+        tokens: &mut Tokens,
+        span: Range<usize>,
+    ) -> Result<Node, CompileError> {
+        let mut node = Node::new(NodeKind::While, None, None, span);
+        self.expect(
+            tokens,
+            TokenKind::Sep(Separator::RoundBracketL),
+            ParseError::NotFoundRoundBracketL,
+        )?;
+        node.cond = Some(Box::new(self.expr(tokens)?));
+        self.expect(
+            tokens,
+            TokenKind::Sep(Separator::RoundBracketR),
+            ParseError::NotFoundRoundBracketR,
+        )?;
+        let then = self.loop_body(tokens)?;
+        node.span.end = then.span.end;
+        node.then = Some(Box::new(then));
+        Ok(node)
+    }
+
+    // "for" "(" expr? ";" expr? ";" expr? ")" stmt
+    fn for_stmt(&mut self, tokens: &mut Tokens, span: Range<usize>) -> Result<Node, CompileError> {
+        let mut node = Node::new(NodeKind::For, None, None, span);
+        self.expect(
+            tokens,
+            TokenKind::Sep(Separator::RoundBracketL),
+            ParseError::NotFoundRoundBracketL,
+        )?;
+        if !consume(tokens, TokenKind::Sep(Separator::SemiColon)) {
+            node.init = Some(Box::new(self.expr(tokens)?));
+            self.expect(
+                tokens,
+                TokenKind::Sep(Separator::SemiColon),
+                ParseError::NeedSemiColon,
+            )?;
+        }
+        if !consume(tokens, TokenKind::Sep(Separator::SemiColon)) {
+            node.cond = Some(Box::new(self.expr(tokens)?));
+            self.expect(
+                tokens,
+                TokenKind::Sep(Separator::SemiColon),
+                ParseError::NeedSemiColon,
+            )?;
+        }
+        if !consume(tokens, TokenKind::Sep(Separator::RoundBracketR)) {
+            node.inc = Some(Box::new(self.expr(tokens)?));
+            self.expect(
+                tokens,
+                TokenKind::Sep(Separator::RoundBracketR),
+                ParseError::NotFoundRoundBracketR,
+            )?;
+        }
+        let then = self.loop_body(tokens)?;
+        node.span.end = then.span.end;
+        node.then = Some(Box::new(then));
+        Ok(node)
+    }
+
+    // body of "while" and "for", where "break" and "continue" are allowed
+    fn loop_body(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        self.loop_depth += 1;
+        let body = self.stmt(tokens);
+        self.loop_depth -= 1;
+        body
+    }
+
+    // assign ("," assign)*
     fn expr(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
-        self.assign(tokens)
+        let mut node = self.assign(tokens)?;
+        while consume(tokens, TokenKind::Sep(Separator::Comma)) {
+            node = Node::binary(NodeKind::Comma, node, self.assign(tokens)?);
+        }
+        Ok(node)
     }
 
+    // conditional (assign-op assign)?
+    // assign-op = "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "<<=" | ">>=" | "&=" | "|=" | "^="
     fn assign(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
-        let mut node = self.equality(tokens)?;
-        if let Some(token) = tokens.peek() {
-            if token.kind == TokenKind::Assign {
+        let node = self.conditional(tokens)?;
+        let Some(token) = tokens.peek() else {
+            return Ok(node);
+        };
+        let op = match token.kind {
+            TokenKind::Assign => {
                 tokens.next();
-                node = Node::new(NodeKind::Assign, Some(node), Some(self.assign(tokens)?));
+                return Ok(Node::binary(NodeKind::Assign, node, self.assign(tokens)?));
             }
+            TokenKind::AddAssign => NodeKind::Add,
+            TokenKind::SubAssign => NodeKind::Sub,
+            TokenKind::MulAssign => NodeKind::Mul,
+            TokenKind::DivAssign => NodeKind::Div,
+            TokenKind::ModAssign => NodeKind::Mod,
+            TokenKind::ShlAssign => NodeKind::Shl,
+            TokenKind::ShrAssign => NodeKind::Shr,
+            TokenKind::AndAssign => NodeKind::BitAnd,
+            TokenKind::OrAssign => NodeKind::BitOr,
+            TokenKind::XorAssign => NodeKind::BitXor,
+            _ => return Ok(node),
+        };
+        tokens.next();
+        let value = self.assign(tokens)?;
+        let span = node.span.start..value.span.end;
+        Ok(Self::assign_op(NodeKind::AssignOp, op, node, value, span))
+    }
+
+    // "target op= value", where the target is evaluated only once by the codegen
+    fn assign_op(
+        kind: NodeKind,
+        op: NodeKind,
+        target: Node,
+        value: Node,
+        span: Range<usize>,
+    ) -> Node {
+        Node::new(kind, Some(Node::binary(op, target, value)), None, span)
+    }
+
+    // log-or ("?" expr ":" conditional)?
+    fn conditional(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let cond = self.log_or(tokens)?;
+        if !consume(tokens, TokenKind::Question) {
+            return Ok(cond);
+        }
+        let then = self.expr(tokens)?;
+        self.expect(tokens, TokenKind::Colon, ParseError::NeedColon)?;
+        let els = self.conditional(tokens)?;
+        let mut node = Node::new(NodeKind::Cond, None, None, cond.span.start..els.span.end);
+        node.cond = Some(Box::new(cond));
+        node.then = Some(Box::new(then));
+        node.els = Some(Box::new(els));
+        Ok(node)
+    }
+
+    // log-and ("||" log-and)*
+    fn log_or(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let mut node = self.log_and(tokens)?;
+        while consume(tokens, TokenKind::OrOr) {
+            node = Node::binary(NodeKind::LogOr, node, self.log_and(tokens)?);
+        }
+        Ok(node)
+    }
+
+    // bit-or ("&&" bit-or)*
+    fn log_and(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let mut node = self.bit_or(tokens)?;
+        while consume(tokens, TokenKind::AndAnd) {
+            node = Node::binary(NodeKind::LogAnd, node, self.bit_or(tokens)?);
+        }
+        Ok(node)
+    }
+
+    // bit-xor ("|" bit-xor)*
+    fn bit_or(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let mut node = self.bit_xor(tokens)?;
+        while consume(tokens, TokenKind::Pipe) {
+            node = Node::binary(NodeKind::BitOr, node, self.bit_xor(tokens)?);
+        }
+        Ok(node)
+    }
+
+    // bit-and ("^" bit-and)*
+    fn bit_xor(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let mut node = self.bit_and(tokens)?;
+        while consume(tokens, TokenKind::Caret) {
+            node = Node::binary(NodeKind::BitXor, node, self.bit_and(tokens)?);
+        }
+        Ok(node)
+    }
+
+    // equality ("&" equality)*
+    fn bit_and(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let mut node = self.equality(tokens)?;
+        while consume(tokens, TokenKind::Amp) {
+            node = Node::binary(NodeKind::BitAnd, node, self.equality(tokens)?);
         }
         Ok(node)
     }
@@ -120,11 +1056,11 @@ impl Parser {
             match token.kind {
                 TokenKind::Eq => {
                     tokens.next();
-                    node = Node::new(NodeKind::Eq, Some(node), Some(self.relational(tokens)?));
+                    node = Node::binary(NodeKind::Eq, node, self.relational(tokens)?);
                 }
                 TokenKind::NotEq => {
                     tokens.next();
-                    node = Node::new(NodeKind::NotEq, Some(node), Some(self.relational(tokens)?));
+                    node = Node::binary(NodeKind::NotEq, node, self.relational(tokens)?);
                 }
                 _ => {
                     break;
@@ -135,24 +1071,50 @@ impl Parser {
     }
 
     fn relational(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
-        let mut node = self.add(tokens)?;
+        let mut node = self.shift(tokens)?;
         while let Some(token) = tokens.peek() {
             match token.kind {
                 TokenKind::Less => {
                     tokens.next();
-                    node = Node::new(NodeKind::Less, Some(node), Some(self.add(tokens)?));
+                    node = Node::binary(NodeKind::Less, node, self.shift(tokens)?);
                 }
                 TokenKind::LessEq => {
                     tokens.next();
-                    node = Node::new(NodeKind::LessEq, Some(node), Some(self.add(tokens)?));
+                    node = Node::binary(NodeKind::LessEq, node, self.shift(tokens)?);
                 }
+                // "a > b" is "b < a", which still spans from a to b
                 TokenKind::Greater => {
                     tokens.next();
-                    node = Node::new(NodeKind::Less, Some(self.add(tokens)?), Some(node));
+                    let rhs = self.shift(tokens)?;
+                    let span = node.span.start..rhs.span.end;
+                    node = Node::new(NodeKind::Less, Some(rhs), Some(node), span);
                 }
                 TokenKind::GreaterEq => {
                     tokens.next();
-                    node = Node::new(NodeKind::LessEq, Some(self.add(tokens)?), Some(node));
+                    let rhs = self.shift(tokens)?;
+                    let span = node.span.start..rhs.span.end;
+                    node = Node::new(NodeKind::LessEq, Some(rhs), Some(node), span);
+                }
+                _ => {
+                    break;
+                }
+            }
+        }
+        Ok(node)
+    }
+
+    // add ("<<" add | ">>" add)*
+    fn shift(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let mut node = self.add(tokens)?;
+        while let Some(token) = tokens.peek() {
+            match token.kind {
+                TokenKind::Shl => {
+                    tokens.next();
+                    node = Node::binary(NodeKind::Shl, node, self.add(tokens)?);
+                }
+                TokenKind::Shr => {
+                    tokens.next();
+                    node = Node::binary(NodeKind::Shr, node, self.add(tokens)?);
                 }
                 _ => {
                     break;
@@ -163,20 +1125,16 @@ impl Parser {
     }
 
     fn add(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
-        // println!("{:#?}", tokens);
         let mut node = self.mul(tokens)?;
         while let Some(token) = tokens.peek() {
-            // println!("expr: {:#?}", token);
             match token.kind {
                 TokenKind::Add => {
-                    // println!("dbg! ok?");
                     tokens.next();
-                    node = Node::new(NodeKind::Add, Some(node), Some(self.mul(tokens)?));
-                    // println!("{:#?}", node);
+                    node = Node::binary(NodeKind::Add, node, self.mul(tokens)?);
                 }
                 TokenKind::Sub => {
                     tokens.next();
-                    node = Node::new(NodeKind::Sub, Some(node), Some(self.mul(tokens)?));
+                    node = Node::binary(NodeKind::Sub, node, self.mul(tokens)?);
                 }
                 _ => {
                     break;
@@ -189,21 +1147,21 @@ impl Parser {
     fn mul(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
         let mut node = self.unary(tokens)?;
         while let Some(token) = tokens.peek() {
-            // println!("mul: {:#?}", token);
             match token.kind {
                 TokenKind::Mul => {
                     tokens.next();
-                    node = Node::new(NodeKind::Mul, Some(node), Some(self.unary(tokens)?));
+                    node = Node::binary(NodeKind::Mul, node, self.unary(tokens)?);
                 }
                 TokenKind::Div => {
                     tokens.next();
-                    node = Node::new(NodeKind::Div, Some(node), Some(self.unary(tokens)?));
+                    node = Node::binary(NodeKind::Div, node, self.unary(tokens)?);
                 }
-                TokenKind::Number(_) => {
-                    return Err(CompileError {
-                        error_type: CompileErrorType::Parsing(ParseError::CannotParse),
-                        pos: Some(token.span.clone()),
-                    })
+                TokenKind::Mod => {
+                    tokens.next();
+                    node = Node::binary(NodeKind::Mod, node, self.unary(tokens)?);
+                }
+                TokenKind::Number(..) => {
+                    return Err(self.error(ParseError::CannotParse, token.span.clone()))
                 }
                 _ => {
                     break;
@@ -215,92 +1173,225 @@ impl Parser {
 
     fn unary(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
         let result;
-        // println!("{:#?}", result);
         if let Some(token) = tokens.peek() {
-            // println!("unary: {:#?}", token);
+            let span = token.span.clone();
             match token.kind {
                 TokenKind::Add => {
-                    // println!("+ {:?}", token.span);
                     tokens.next();
                     result = self.unary(tokens);
                 }
                 TokenKind::Sub => {
                     tokens.next();
-                    result = Ok(Node::new(
-                        NodeKind::Sub,
-                        Some(Node::new(NodeKind::Number(0), None, None)),
-                        Some(self.unary(tokens)?),
-                    ));
+                    let zero = Node::new(NodeKind::Number(0), None, None, span);
+                    result = Ok(Node::binary(NodeKind::Sub, zero, self.unary(tokens)?));
+                }
+                TokenKind::Amp => {
+                    tokens.next();
+                    result = Ok(Self::prefix(NodeKind::Addr, span, self.unary(tokens)?));
+                }
+                TokenKind::Mul => {
+                    tokens.next();
+                    result = Ok(Self::prefix(NodeKind::Deref, span, self.unary(tokens)?));
+                }
+                TokenKind::Not => {
+                    tokens.next();
+                    result = Ok(Self::prefix(NodeKind::Not, span, self.unary(tokens)?));
+                }
+                TokenKind::Tilde => {
+                    tokens.next();
+                    result = Ok(Self::prefix(NodeKind::BitNot, span, self.unary(tokens)?));
+                }
+                // "++a" is "a += 1", and "--a" is "a -= 1"
+                TokenKind::Inc | TokenKind::Dec => {
+                    let op = if token.kind == TokenKind::Inc {
+                        NodeKind::Add
+                    } else {
+                        NodeKind::Sub
+                    };
+                    tokens.next();
+                    let target = self.unary(tokens)?;
+                    let one = Node::new(NodeKind::Number(1), None, None, span.clone());
+                    let span = span.start..target.span.end;
+                    result = Ok(Self::assign_op(NodeKind::AssignOp, op, target, one, span));
+                }
+                TokenKind::Sizeof => {
+                    tokens.next();
+                    result = self.sizeof(tokens, span);
                 }
                 _ => {
-                    result = self.primary(tokens);
+                    result = self.postfix(tokens);
                 }
             }
         } else {
-            return Err(CompileError {
-                error_type: CompileErrorType::Parsing(ParseError::TrailingOp),
-                pos: None,
-            });
+            return Err(self.error(ParseError::TrailingOp, self.eof.clone()));
         }
         result
     }
 
+    // unary operator, which spans from the operator to the end of the operand
+    fn prefix(kind: NodeKind, op: Range<usize>, operand: Node) -> Node {
+        let span = op.start..operand.span.end;
+        Node::new(kind, Some(operand), None, span)
+    }
+
+    // "sizeof" "(" declspec abstract-declarator ")" | "sizeof" unary
+    fn sizeof(&mut self, tokens: &mut Tokens, span: Range<usize>) -> Result<Node, CompileError> {
+        let Some(paren) = consume_span(tokens, TokenKind::Sep(Separator::RoundBracketL)) else {
+            return Ok(Self::prefix(NodeKind::Sizeof, span, self.unary(tokens)?));
+        };
+        if is_typename(tokens) {
+            let base = self.declspec(tokens)?;
+            let ty = self.abstract_declarator(tokens, base)?;
+            let end = self.expect(
+                tokens,
+                TokenKind::Sep(Separator::RoundBracketR),
+                ParseError::NotFoundRoundBracketR,
+            )?;
+            let size = NodeKind::Number(ty.size() as i64);
+            return Ok(Node::new(size, None, None, span.start..end.end).with_type(Type::ULong));
+        }
+        // "(" expr ")" is a primary, which may be followed by postfix operators
+        let mut node = self.expr(tokens)?;
+        let end = self.expect(
+            tokens,
+            TokenKind::Sep(Separator::RoundBracketR),
+            ParseError::NotFoundRoundBracketR,
+        )?;
+        node.span = paren.start..end.end;
+        let node = self.postfix_tail(tokens, node)?;
+        Ok(Self::prefix(NodeKind::Sizeof, span, node))
+    }
+
+    // primary ("[" expr "]" | "++" | "--")*
+    fn postfix(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
+        let node = self.primary(tokens)?;
+        self.postfix_tail(tokens, node)
+    }
+
+    fn postfix_tail(&mut self, tokens: &mut Tokens, mut node: Node) -> Result<Node, CompileError> {
+        loop {
+            let op = match tokens.peek().map(|token| token.kind) {
+                Some(TokenKind::Inc) => NodeKind::Add,
+                Some(TokenKind::Dec) => NodeKind::Sub,
+                Some(TokenKind::Sep(Separator::SquareBracketL)) => {
+                    tokens.next();
+                    node = self.subscript(tokens, node)?;
+                    continue;
+                }
+                _ => return Ok(node),
+            };
+            let end = self.peek_span(tokens);
+            tokens.next();
+            let one = Node::new(NodeKind::Number(1), None, None, end.clone());
+            let span = node.span.start..end.end;
+            node = Self::assign_op(NodeKind::PostAssignOp, op, node, one, span);
+        }
+    }
+
+    // x[y] is short for *(x+y)
+    fn subscript(&mut self, tokens: &mut Tokens, node: Node) -> Result<Node, CompileError> {
+        let index = self.expr(tokens)?;
+        let end = self.expect(
+            tokens,
+            TokenKind::Sep(Separator::SquareBracketR),
+            ParseError::NotFoundSquareBracketR,
+        )?;
+        let span = node.span.start..end.end;
+        Ok(Node::new(
+            NodeKind::Deref,
+            Some(Node::binary(NodeKind::Add, node, index)),
+            None,
+            span,
+        ))
+    }
+
     fn primary(&mut self, tokens: &mut Tokens) -> Result<Node, CompileError> {
-        let node;
+        let mut node;
         if let Some(token) = tokens.peek() {
-            // println!("primary: {:#?}", token);
-            let span = &token.span;
-            // println!("{:?}", token);
+            let span = token.span.clone();
             if token.kind == TokenKind::Sep(Separator::RoundBracketL) {
                 tokens.next();
                 node = self.expr(tokens)?;
-                if let Some(token) = tokens.peek() {
-                    let span = &token.span;
-                    if token.kind != TokenKind::Sep(Separator::RoundBracketR) {
-                        return Err(CompileError {
-                            error_type: CompileErrorType::Parsing(
-                                ParseError::NotFoundRoundBracketR,
-                            ),
-                            pos: Some(span.clone()),
-                        });
-                    } else {
-                        tokens.next();
-                    }
-                } else {
+                let end = self.expect(
+                    tokens,
+                    TokenKind::Sep(Separator::RoundBracketR),
+                    ParseError::NotFoundRoundBracketR,
+                )?;
+                node.span = span.start..end.end;
+            } else if let TokenKind::Number(num, ty) = token.kind {
+                tokens.next();
+                node = Node::new(NodeKind::Number(num), None, None, span).with_type(ty.into());
+            } else if let TokenKind::Str = token.kind {
+                let text = &token.text;
+                let Some(mut data) = unescape(&text[1..text.len() - 1]) else {
                     return Err(CompileError {
-                        error_type: CompileErrorType::Parsing(ParseError::NotFoundRoundBracketR),
-                        pos: None,
+                        error_type: CompileErrorType::Tokenizing(TokenizeError::InvalidEscape),
+                        pos: Some(span),
                     });
-                }
-            } else if let TokenKind::Number(num) = token.kind {
+                };
+                data.push(b'\0');
                 tokens.next();
-                node = Node::new(NodeKind::Number(num), None, None);
+                node = self.new_string_literal(data, span);
             } else if let TokenKind::Ident = token.kind {
+                let ident = token.text.clone();
+                tokens.next();
+                if consume(tokens, TokenKind::Sep(Separator::RoundBracketL)) {
+                    return self.funcall(tokens, &ident, span);
+                }
                 // Convert `ident` -> `var`
-                let ident = token.text;
                 // Search offset by ident name
-                #[allow(clippy::map_entry)]
-                let offset = if !self.locals.contains_key(&ident.to_string()) {
-                    let offset = self.offset();
-                    self.locals.insert(ident.to_string(), LocalVar { offset });
-                    offset
-                } else {
-                    self.locals[&ident.to_string()].offset
+                if let Some(var) = self.find_var(&ident) {
+                    let kind = NodeKind::Var(var.offset);
+                    return Ok(Node::new(kind, None, None, span).with_type(var.ty.clone()));
+                }
+                return match self.find_global(&ident) {
+                    Some(var) => {
+                        let mut node = Node::new(NodeKind::GlobalVar, None, None, span)
+                            .with_type(var.ty.clone());
+                        node.name = Some(ident);
+                        Ok(node)
+                    }
+                    None => Err(self.error(ParseError::UndefinedVar, span)),
                 };
-                tokens.next();
-                return Ok(Node::new(NodeKind::Var(offset), None, None));
             } else {
-                return Err(CompileError {
-                    error_type: CompileErrorType::Parsing(ParseError::NotNumber),
-                    pos: Some(span.clone()),
-                });
+                return Err(self.error(ParseError::NotNumber, span));
             }
         } else {
-            return Err(CompileError {
-                error_type: CompileErrorType::Parsing(ParseError::TrailingOp),
-                pos: None,
-            });
+            return Err(self.error(ParseError::TrailingOp, self.eof.clone()));
+        }
+        Ok(node)
+    }
+
+    // ident "(" (assign ("," assign)*)? ")"
+    fn funcall(
+        &mut self,
+        tokens: &mut Tokens,
+        name: &str,
+        span: Range<usize>,
+    ) -> Result<Node, CompileError> {
+        let mut node = Node::new(NodeKind::FuncCall, None, None, span);
+        node.name = Some(name.to_string());
+        // functions which are not declared return int
+        node.ty = self.return_types.get(name).cloned();
+        if let Some(end) = consume_span(tokens, TokenKind::Sep(Separator::RoundBracketR)) {
+            node.span.end = end.end;
+            return Ok(node);
+        }
+        loop {
+            if node.args.len() == MAX_ARGS {
+                return Err(self.error(ParseError::TooManyArgs, self.peek_span(tokens)));
+            }
+            node.args.push(self.assign(tokens)?);
+            if !consume(tokens, TokenKind::Sep(Separator::Comma)) {
+                break;
+            }
         }
+        let end = self.expect(
+            tokens,
+            TokenKind::Sep(Separator::RoundBracketR),
+            ParseError::NotFoundRoundBracketR,
+        )?;
+        node.span.end = end.end;
         Ok(node)
     }
 }
@@ -0,0 +1,1331 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::errors::{CompileError, CompileErrorType, PreprocessError};
+use crate::parser;
+use crate::tokenizer::{unescape, NumberType, RawStream, Separator, Token, TokenKind};
+
+// deeper #include is an error, as it is most likely recursive
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+// A file read in a compilation, placed at `start` in the source map
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    pub src: String,
+    pub start: usize,
+    lines: Vec<usize>,        // offset of the start of each line
+    markers: Vec<LineMarker>, // '#line' and line markers, in order
+}
+
+// The lines after `after` are counted from `line` in the file `name`
+#[derive(Debug)]
+struct LineMarker {
+    after: usize,
+    line: usize,
+    name: String,
+}
+
+impl SourceFile {
+    // 1-based line and column of `offset`
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.lines.partition_point(|&start| start <= offset);
+        let line_start = self.lines[line - 1];
+        let column = self.src[line_start..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    // The file name, line and column of `offset`, as told by the last '#line' before it
+    pub fn location(&self, offset: usize) -> (&str, usize, usize) {
+        let (line, column) = self.line_column(offset);
+        let index = self.markers.partition_point(|marker| marker.after < line);
+        match index.checked_sub(1).map(|index| &self.markers[index]) {
+            Some(marker) => (&marker.name, marker.line + line - marker.after - 1, column),
+            None => (&self.name, line, column),
+        }
+    }
+}
+
+// Every file of a compilation, one after another. A position in the source map
+// tells the file and the offset in it, so token spans can point into included files.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    // Add a file, and return its start. Files are one byte apart,
+    // so that the end of a file is not the start of the next one.
+    fn add(&mut self, name: &str, src: &str) -> usize {
+        let start = self
+            .files
+            .last()
+            .map_or(0, |file| file.start + file.src.len() + 1);
+        let lines = [0]
+            .into_iter()
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.files.push(SourceFile {
+            name: name.to_string(),
+            src: src.to_string(),
+            start,
+            lines,
+            markers: vec![],
+        });
+        start
+    }
+
+    // The lines after the one at `pos` are counted from `line`, in the file `name`
+    // or in the same file
+    fn add_marker(&mut self, pos: usize, line: usize, name: Option<String>) {
+        let index = self.files.partition_point(|file| file.start <= pos);
+        let Some(file) = index.checked_sub(1).map(|index| &mut self.files[index]) else {
+            return;
+        };
+        let after = file.line_column(pos - file.start).0;
+        let name = name.unwrap_or_else(|| file.location(pos - file.start).0.to_string());
+        file.markers.push(LineMarker { after, line, name });
+    }
+
+    // Every file, the main file first
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    // The file containing `pos`, and the offset of `pos` in it
+    pub fn origin(&self, pos: usize) -> Option<(&SourceFile, usize)> {
+        let index = self
+            .files
+            .partition_point(|file| file.start <= pos)
+            .checked_sub(1)?;
+        let file = &self.files[index];
+        Some((file, pos - file.start))
+    }
+
+    // Render the error in the file it is found in.
+    // Errors without position are reported for the main file.
+    pub fn render(&self, error: &CompileError) -> String {
+        let origin = error
+            .pos
+            .as_ref()
+            .and_then(|pos| Some((pos, self.origin(pos.start)?)));
+        match (origin, self.files.first()) {
+            (Some((pos, (file, offset))), _) => {
+                let span = offset..offset + pos.len();
+                let (name, line, _) = file.location(offset);
+                error.render_as(name, line, &file.src, Some(&span))
+            }
+            (None, Some(main)) => error.render_at(&main.name, &main.src, None),
+            (None, None) => error.render_at("", "", None),
+        }
+    }
+
+    // The text of preprocessed tokens, with line markers like '# 12 "foo.h"'
+    // where tokens come from another file or from lines far below
+    pub fn preprocessed(&self, tokens: &[Token]) -> String {
+        let mut text = String::new();
+        let mut current = None; // file name and line of the last token
+        let mut last: Option<&Token> = None;
+        for token in tokens {
+            let Some((file, offset)) = self.origin(token.span.start) else {
+                continue;
+            };
+            let (name, line, column) = file.location(offset);
+            match current {
+                Some((current_name, current_line))
+                    if current_name == name && current_line == line =>
+                {
+                    // tokens of a macro expansion are always separated, and adjacent
+                    // tokens are separated when they would be read as other tokens
+                    let adjacent = last.is_some_and(|last| last.span.end == token.span.start);
+                    let expanded =
+                        file.src.get(offset..offset + token.span.len()) != Some(&token.text);
+                    if !adjacent
+                        || expanded
+                        || last.is_some_and(|last| !lexes_apart(&last.text, &token.text))
+                    {
+                        text.push(' ');
+                    }
+                }
+                Some((current_name, current_line))
+                    if current_name == name && current_line < line && line - current_line <= 8 =>
+                {
+                    text += &"\n".repeat(line - current_line);
+                    text += &" ".repeat(column - 1);
+                }
+                _ => {
+                    if current.is_some() {
+                        text.push('\n');
+                    }
+                    text += &format!("# {} \"{}\"\n", line, name);
+                    text += &" ".repeat(column - 1);
+                }
+            }
+            current = Some((name, line));
+            last = Some(token);
+            text += &token.text;
+        }
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// Whether `first` and `second` written together are still read as the two tokens
+fn lexes_apart(first: &str, second: &str) -> bool {
+    let joined = format!("{}{}", first, second);
+    let texts: Vec<String> = RawStream::new(&joined)
+        .map_while(|token| token.ok().map(|token| token.text))
+        .collect();
+    texts == [first, second]
+}
+
+fn shift(mut token: Token, offset: usize) -> Token {
+    token.span = token.span.start + offset..token.span.end + offset;
+    token
+}
+
+fn shift_error(mut error: CompileError, offset: usize) -> CompileError {
+    error.pos = error.pos.map(|pos| pos.start + offset..pos.end + offset);
+    error
+}
+
+fn preprocess_error(error: PreprocessError, span: Range<usize>) -> CompileError {
+    CompileError {
+        error_type: CompileErrorType::Preprocessing(error),
+        pos: Some(span),
+    }
+}
+
+// identifiers and keywords can be macro names
+fn is_macro_name(token: &Token) -> bool {
+    token
+        .text
+        .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+}
+
+// The tokens of a macro body are placed at the macro, and arguments where they are written
+fn placed(token: &Token, span: &Range<usize>) -> Token {
+    Token {
+        span: span.clone(),
+        ..token.clone()
+    }
+}
+
+fn number(value: i64, span: Range<usize>) -> Token {
+    Token {
+        text: value.to_string(),
+        kind: TokenKind::Number(value, NumberType::Int),
+        span,
+    }
+}
+
+// Integers of '#if' are intmax_t, or uintmax_t if they have the suffix 'u',
+// or are too large for intmax_t
+fn intmax(mut token: Token) -> Token {
+    if let TokenKind::Number(value, ty) = token.kind {
+        let suffix = !token.text.starts_with('\'') && token.text.contains(['u', 'U']);
+        let ty = if suffix || ty == NumberType::ULong {
+            NumberType::ULong
+        } else {
+            NumberType::Long
+        };
+        token.kind = TokenKind::Number(value, ty);
+    }
+    token
+}
+
+// a string literal of `text`, with '"' and '\' escaped
+fn string(text: &str, span: Range<usize>) -> Token {
+    Token {
+        text: format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+        kind: TokenKind::Str,
+        span,
+    }
+}
+
+// '#': a string literal of the argument as written, with the spaces between tokens
+fn stringize(arg: &[PpToken], span: Range<usize>) -> Token {
+    let mut text = String::new();
+    for (i, token) in arg.iter().enumerate() {
+        if i > 0 && arg[i - 1].token.span.end < token.token.span.start {
+            text.push(' ');
+        }
+        text.push_str(&token.token.text);
+    }
+    string(&text, span)
+}
+
+// The parameters after "#define name(", and whether the macro is variadic.
+// The error is the span of the wrong token, or None at the end of the line.
+fn macro_params(
+    tokens: &mut impl Iterator<Item = Token>,
+) -> Result<(Vec<String>, bool), Option<Range<usize>>> {
+    let rparen = TokenKind::Sep(Separator::RoundBracketR);
+    let mut params = vec![];
+    let mut token = tokens.next().ok_or(None)?;
+    if token.kind == rparen {
+        return Ok((params, false));
+    }
+    loop {
+        if token.kind == TokenKind::Ellipsis {
+            params.push("__VA_ARGS__".to_string());
+            let token = tokens.next().ok_or(None)?;
+            return if token.kind == rparen {
+                Ok((params, true))
+            } else {
+                Err(Some(token.span))
+            };
+        }
+        if !is_macro_name(&token) {
+            return Err(Some(token.span));
+        }
+        params.push(token.text);
+        let next = tokens.next().ok_or(None)?;
+        match next.kind {
+            TokenKind::Sep(Separator::Comma) => token = tokens.next().ok_or(None)?,
+            kind if kind == rparen => return Ok((params, false)),
+            _ => return Err(Some(next.span)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Option<Vec<String>>, // None for an object-like macro
+    variadic: bool,              // the last parameter is "__VA_ARGS__"
+    body: Vec<Token>,
+}
+
+// A token with its hideset: the macros it is expanded from,
+// which are not expanded again, so that a recursive macro stops
+#[derive(Debug, Clone)]
+struct PpToken {
+    token: Token,
+    hideset: Vec<String>,
+}
+
+impl PpToken {
+    fn new(token: Token) -> Self {
+        PpToken {
+            token,
+            hideset: vec![],
+        }
+    }
+
+    fn is(&self, kind: TokenKind) -> bool {
+        self.token.kind == kind
+    }
+}
+
+// A file being preprocessed
+struct Lexer<'a> {
+    stream: RawStream<'a>,
+    path: &'a Path,
+    start: usize,      // start of the file in the source map
+    conditions: usize, // number of open '#if' when the file is included
+}
+
+// An '#if' and its '#elif' and '#else'
+#[derive(Debug)]
+struct Condition {
+    taken: bool,        // one of the groups is already included
+    seen_else: bool,    // the '#else' is already read
+    span: Range<usize>, // the '#if'
+}
+
+// Run the directives of the source files, and expand macros, before parsing
+#[derive(Debug, Default)]
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,    // searched by #include, given by -I
+    sources: SourceMap,             // the main file and the included files
+    macros: HashMap<String, Macro>, // name -> definition
+    pending: VecDeque<PpToken>,     // tokens to read before the file, made by expansions
+    conditions: Vec<Condition>,     // open '#if', innermost last
+    once: HashSet<PathBuf>,         // files with '#pragma once'
+    depth: usize,                   // nesting of #include
+    command_line: String,           // directives of -D and -U
+    counter: i64,                   // next value of __COUNTER__
+    errors: Vec<CompileError>,      // errors recovered from so far
+}
+
+impl Preprocessor {
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        Preprocessor {
+            include_paths,
+            ..Default::default()
+        }
+    }
+
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    // -D: "NAME" defines NAME as 1, and "NAME=VALUE" as VALUE
+    pub fn define(&mut self, definition: &str) {
+        let (name, value) = definition.split_once('=').unwrap_or((definition, "1"));
+        self.command_line += &format!("#define {} {}\n", name, value);
+    }
+
+    // -U
+    pub fn undefine(&mut self, name: &str) {
+        self.command_line += &format!("#undef {}\n", name);
+    }
+
+    // Preprocess the main file `name`, whose contents are `src`
+    pub fn run(&mut self, name: &str, src: &str) -> Result<Vec<Token>, Vec<CompileError>> {
+        let mut output = vec![];
+        // the main file is the first one in the source map
+        let start = self.sources.add(name, src);
+        let command_line = std::mem::take(&mut self.command_line);
+        if !command_line.is_empty() {
+            self.file(Path::new("<command line>"), &command_line, &mut output);
+        }
+        self.process(Path::new(name), src, start, &mut output);
+        if self.errors.is_empty() {
+            Ok(output.into_iter().map(|token| token.token).collect())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn file(&mut self, path: &Path, src: &str, output: &mut Vec<PpToken>) {
+        let start = self.sources.add(&path.to_string_lossy(), src);
+        self.process(path, src, start, output);
+    }
+
+    // Preprocess a file placed at `start` in the source map
+    fn process(&mut self, path: &Path, src: &str, start: usize, output: &mut Vec<PpToken>) {
+        let mut lexer = Lexer {
+            stream: RawStream::new(src),
+            path,
+            start,
+            conditions: self.conditions.len(),
+        };
+        loop {
+            let token = match self.pending.pop_front() {
+                Some(token) => token,
+                None => match self.lex(&mut lexer) {
+                    // '#' is a directive only at the start of a line
+                    Some(token) if token.kind == TokenKind::Hash && lexer.stream.line_start() => {
+                        self.directive(&mut lexer, output);
+                        continue;
+                    }
+                    Some(token) => PpToken::new(token),
+                    None => break,
+                },
+            };
+            self.expand(token, Some(&mut lexer), output);
+        }
+        for condition in self.conditions.split_off(lexer.conditions) {
+            self.errors.push(preprocess_error(
+                PreprocessError::UnterminatedCondition,
+                condition.span,
+            ));
+        }
+    }
+
+    // The next token of the file, placed in the source map
+    fn lex(&mut self, lexer: &mut Lexer) -> Option<Token> {
+        loop {
+            match lexer.stream.next()? {
+                Ok(token) => return Some(shift(token, lexer.start)),
+                Err(error) => self.errors.push(shift_error(error, lexer.start)),
+            }
+        }
+    }
+
+    // The next token made by expansions, or of the file
+    fn next_token(&mut self, lexer: Option<&mut Lexer>) -> Option<PpToken> {
+        match self.pending.pop_front() {
+            Some(token) => Some(token),
+            None => self.lex(lexer?).map(PpToken::new),
+        }
+    }
+
+    fn next_is_lparen(&self, lexer: Option<&Lexer>) -> bool {
+        let lparen = TokenKind::Sep(Separator::RoundBracketL);
+        match self.pending.front() {
+            Some(token) => token.is(lparen),
+            // look ahead with a copy of the stream
+            None => lexer.is_some_and(|lexer| {
+                matches!(lexer.stream.clone().next(), Some(Ok(token)) if token.kind == lparen)
+            }),
+        }
+    }
+
+    // Output `token`, or read its replacement again if it is a macro
+    fn expand(&mut self, token: PpToken, lexer: Option<&mut Lexer>, output: &mut Vec<PpToken>) {
+        let name = &token.token.text;
+        if token.hideset.contains(name) {
+            output.push(token);
+            return;
+        }
+        if let Some(builtin) = self.builtin(&token.token) {
+            output.push(PpToken {
+                token: builtin,
+                hideset: token.hideset,
+            });
+            return;
+        }
+        let Some(definition) = self.macros.get(name).cloned() else {
+            output.push(token);
+            return;
+        };
+        let span = token.token.span.clone();
+        let (expanded, mut hideset) = match &definition.params {
+            None => {
+                let Some(body) = self.subst(&definition, &[], &span) else {
+                    return;
+                };
+                (body, token.hideset.clone())
+            }
+            // a function-like macro without arguments is not expanded
+            Some(_) if !self.next_is_lparen(lexer.as_deref()) => {
+                output.push(token);
+                return;
+            }
+            Some(_) => {
+                let Some((args, rparen)) = self.macro_args(&token, &definition, lexer) else {
+                    return;
+                };
+                let Some(body) = self.subst(&definition, &args, &span) else {
+                    return;
+                };
+                // the macros hiding both the name and ')'
+                let hideset = token
+                    .hideset
+                    .iter()
+                    .filter(|name| rparen.hideset.contains(name))
+                    .cloned()
+                    .collect();
+                (body, hideset)
+            }
+        };
+        hideset.push(name.clone());
+        for mut expanded in expanded.into_iter().rev() {
+            expanded.hideset.extend(hideset.iter().cloned());
+            self.pending.push_front(expanded);
+        }
+    }
+
+    // __LINE__, __FILE__ and __COUNTER__
+    fn builtin(&mut self, token: &Token) -> Option<Token> {
+        let span = token.span.clone();
+        match token.text.as_str() {
+            "__LINE__" => {
+                let line = self
+                    .sources
+                    .origin(span.start)
+                    .map_or(0, |(file, offset)| file.location(offset).1);
+                Some(number(line as i64, span))
+            }
+            "__FILE__" => {
+                let name = self
+                    .sources
+                    .origin(span.start)
+                    .map_or("", |(file, offset)| file.location(offset).0);
+                Some(string(name, span))
+            }
+            "__COUNTER__" => {
+                self.counter += 1;
+                Some(number(self.counter - 1, span))
+            }
+            _ => None,
+        }
+    }
+
+    // The arguments of a function-like macro, and the closing ')'
+    fn macro_args(
+        &mut self,
+        name: &PpToken,
+        definition: &Macro,
+        mut lexer: Option<&mut Lexer>,
+    ) -> Option<(Vec<Vec<PpToken>>, PpToken)> {
+        let params = definition.params.as_deref().unwrap_or_default();
+        let error = |error| preprocess_error(error, name.token.span.clone());
+        self.next_token(lexer.as_deref_mut()); // '('
+        let mut args = vec![vec![]];
+        let mut depth = 0;
+        let rparen = loop {
+            let Some(token) = self.next_token(lexer.as_deref_mut()) else {
+                let name = name.token.text.clone();
+                self.errors
+                    .push(error(PreprocessError::UnterminatedArgs(name)));
+                return None;
+            };
+            match token.token.kind {
+                TokenKind::Sep(Separator::RoundBracketL) => depth += 1,
+                TokenKind::Sep(Separator::RoundBracketR) if depth == 0 => break token,
+                TokenKind::Sep(Separator::RoundBracketR) => depth -= 1,
+                // the commas of the variadic arguments are kept
+                TokenKind::Sep(Separator::Comma)
+                    if depth == 0 && (!definition.variadic || args.len() < params.len()) =>
+                {
+                    args.push(vec![]);
+                    continue;
+                }
+                _ => {}
+            }
+            args.last_mut().expect("no argument").push(token);
+        };
+        // "f()" has no arguments, and the variadic arguments may be omitted
+        if params.is_empty() && args.len() == 1 && args[0].is_empty() {
+            args.clear();
+        }
+        if definition.variadic && args.len() + 1 == params.len() {
+            args.push(vec![]);
+        }
+        if args.len() != params.len() {
+            let name = name.token.text.clone();
+            self.errors
+                .push(error(PreprocessError::WrongArgCount(name)));
+            return None;
+        }
+        Some((args, rparen))
+    }
+
+    // The body of a macro with the arguments substituted, and the operands of '##' pasted.
+    // Arguments are expanded, unless they are operands of '#' or '##'.
+    fn subst(
+        &mut self,
+        definition: &Macro,
+        args: &[Vec<PpToken>],
+        span: &Range<usize>,
+    ) -> Option<Vec<PpToken>> {
+        let params = definition.params.as_deref().unwrap_or_default();
+        let param = |token: &Token| params.iter().position(|param| *param == token.text);
+        let body = &definition.body;
+        let mut result: Vec<PpToken> = vec![];
+        let mut i = 0;
+        while i < body.len() {
+            let token = &body[i];
+            let next = body.get(i + 1);
+            // the macro is checked by `define`, '#' is stringizing only in function-like macros
+            if token.kind == TokenKind::Hash && definition.params.is_some() {
+                let arg = &args[next.and_then(param).expect("'#' without parameter")];
+                result.push(PpToken::new(stringize(arg, span.clone())));
+                i += 2;
+                continue;
+            }
+            if token.kind == TokenKind::HashHash {
+                let rhs = body.get(i + 1).expect("'##' at the end");
+                let mut rhs = match param(rhs) {
+                    Some(index) => args[index].clone().into_iter(),
+                    None => vec![PpToken::new(placed(rhs, span))].into_iter(),
+                };
+                match (result.pop(), rhs.next()) {
+                    (Some(lhs), Some(first)) => result.push(self.paste(lhs, first)?),
+                    (lhs, first) => result.extend(lhs.into_iter().chain(first)),
+                }
+                result.extend(rhs);
+                i += 2;
+                continue;
+            }
+            let Some(index) = param(token) else {
+                result.push(PpToken::new(placed(token, span)));
+                i += 1;
+                continue;
+            };
+            let arg = &args[index];
+            if next.is_some_and(|next| next.kind == TokenKind::HashHash) {
+                if !arg.is_empty() {
+                    result.extend(arg.iter().cloned());
+                    i += 1;
+                } else if let Some(rhs) = body.get(i + 2).and_then(param) {
+                    // an empty argument is pasted to nothing
+                    result.extend(args[rhs].iter().cloned());
+                    i += 3;
+                } else {
+                    i += 2;
+                }
+                continue;
+            }
+            result.extend(self.expand_list(arg.clone()));
+            i += 1;
+        }
+        Some(result)
+    }
+
+    // '##': the token of the text of both tokens
+    fn paste(&mut self, lhs: PpToken, rhs: PpToken) -> Option<PpToken> {
+        let text = lhs.token.text.clone() + &rhs.token.text;
+        let mut stream = RawStream::new(&text);
+        match (stream.next(), stream.next()) {
+            (Some(Ok(mut token)), None) => {
+                token.span = lhs.token.span;
+                Some(PpToken {
+                    token,
+                    hideset: lhs.hideset,
+                })
+            }
+            _ => {
+                let error = PreprocessError::InvalidPaste(text);
+                self.errors.push(preprocess_error(error, lhs.token.span));
+                None
+            }
+        }
+    }
+
+    // Expand the tokens apart from the file, as arguments are
+    fn expand_list(&mut self, tokens: Vec<PpToken>) -> Vec<PpToken> {
+        let pending = std::mem::replace(&mut self.pending, tokens.into());
+        let mut output = vec![];
+        while let Some(token) = self.pending.pop_front() {
+            self.expand(token, None, &mut output);
+        }
+        self.pending = pending;
+        output
+    }
+
+    // Tokenize the rest of a directive line, which starts at `offset` in the source map
+    fn tokenize(&mut self, line: &str, offset: usize) -> Option<Vec<Token>> {
+        let mut tokens = vec![];
+        for token in RawStream::new(line) {
+            match token {
+                Ok(token) => tokens.push(shift(token, offset)),
+                Err(error) => {
+                    self.errors.push(shift_error(error, offset));
+                    return None;
+                }
+            }
+        }
+        Some(tokens)
+    }
+
+    // The name of a directive, and the rest of the line with its offset in the source map
+    fn directive_line<'l>(&mut self, lexer: &mut Lexer<'l>) -> Option<(Token, &'l str, usize)> {
+        let (line, span) = lexer.stream.take_line();
+        let offset = lexer.start + span.start;
+        let mut stream = RawStream::new(line);
+        let name = match stream.next()? {
+            Ok(name) => shift(name, offset),
+            Err(error) => {
+                self.errors.push(shift_error(error, offset));
+                return None;
+            }
+        };
+        let (rest, span) = stream.take_line();
+        Some((name, rest, offset + span.start))
+    }
+
+    // The line of a directive after '#'
+    fn directive(&mut self, lexer: &mut Lexer, output: &mut Vec<PpToken>) {
+        // '#' alone is a null directive
+        let Some((name, rest, offset)) = self.directive_line(lexer) else {
+            return;
+        };
+        // the file name and the message are not tokens
+        match name.text.as_str() {
+            "include" => {
+                let span = offset..offset + rest.len();
+                return self.include(lexer.path, rest, span, output);
+            }
+            "error" => {
+                let error = PreprocessError::Error(rest.trim().to_string());
+                return self.errors.push(preprocess_error(error, name.span));
+            }
+            _ => {}
+        }
+        let Some(args) = self.tokenize(rest, offset) else {
+            return;
+        };
+        let eof = offset + rest.len()..offset + rest.len();
+        match name.text.as_str() {
+            "define" => self.define_macro(&name, args),
+            "undef" => match args.first() {
+                Some(token) if is_macro_name(token) => {
+                    self.macros.remove(&token.text);
+                }
+                _ => self
+                    .errors
+                    .push(preprocess_error(PreprocessError::NoMacroName, name.span)),
+            },
+            "if" => {
+                let taken = self.condition(args, eof);
+                self.open(taken, name.span, lexer);
+            }
+            "ifdef" | "ifndef" => match args.first() {
+                Some(token) if is_macro_name(token) => {
+                    let defined = self.macros.contains_key(&token.text);
+                    self.open(defined == (name.text == "ifdef"), name.span, lexer);
+                }
+                _ => self
+                    .errors
+                    .push(preprocess_error(PreprocessError::NoMacroName, name.span)),
+            },
+            // the group before is included, so the rest is skipped
+            "elif" | "else" if self.conditions.len() > lexer.conditions => {
+                self.check_else(&name);
+                self.skip(lexer);
+            }
+            "endif" if self.conditions.len() > lexer.conditions => {
+                self.conditions.pop();
+            }
+            "elif" | "else" | "endif" => self.errors.push(preprocess_error(
+                PreprocessError::Unmatched(name.text),
+                name.span,
+            )),
+            // '#line' and the line markers of -E output, like '# 12 "foo.h" 1'
+            "line" => self.line_marker(&args, false, name.span, eof.end),
+            _ if matches!(name.kind, TokenKind::Number(..)) => {
+                let span = name.span.clone();
+                self.line_marker(&[vec![name], args].concat(), true, span, eof.end)
+            }
+            // other pragmas are ignored
+            "pragma" => {
+                if args.first().is_some_and(|arg| arg.text == "once") {
+                    self.once.insert(canonical(lexer.path));
+                }
+            }
+            _ => self.errors.push(preprocess_error(
+                PreprocessError::InvalidDirective,
+                name.span,
+            )),
+        }
+    }
+
+    // A line number and an optional file name, which the lines after the directive
+    // ending at `end` are counted in. Line markers also have flags after them.
+    fn line_marker(&mut self, tokens: &[Token], flags: bool, span: Range<usize>, end: usize) {
+        let is_number = |token: &Token| matches!(token.kind, TokenKind::Number(..));
+        let name = match tokens {
+            [line] if is_number(line) => None,
+            [line, file, rest @ ..]
+                if is_number(line)
+                    && file.kind == TokenKind::Str
+                    && (rest.is_empty() || flags && rest.iter().all(is_number)) =>
+            {
+                unescape(&file.text[1..file.text.len() - 1])
+                    .map(|name| String::from_utf8_lossy(&name).into_owned())
+            }
+            _ => {
+                return self
+                    .errors
+                    .push(preprocess_error(PreprocessError::InvalidLine, span))
+            }
+        };
+        if let Some(TokenKind::Number(line, _)) = tokens.first().map(|token| token.kind) {
+            self.sources.add_marker(end, line as usize, name);
+        }
+    }
+
+    fn open(&mut self, taken: bool, span: Range<usize>, lexer: &mut Lexer) {
+        self.conditions.push(Condition {
+            taken,
+            seen_else: false,
+            span,
+        });
+        if !taken {
+            self.skip(lexer);
+        }
+    }
+
+    // Skip the lines of groups which are not included,
+    // until the '#elif' or '#else' of a group to include, or '#endif'
+    fn skip(&mut self, lexer: &mut Lexer) {
+        let mut depth = 0; // nesting of '#if' in the skipped lines
+        while let Some(token) = lexer.stream.next() {
+            let hash = matches!(token, Ok(token) if token.kind == TokenKind::Hash);
+            if !(hash && lexer.stream.line_start()) {
+                // the tokens of skipped lines are not checked
+                lexer.stream.take_line();
+                continue;
+            }
+            let Some((name, rest, offset)) = self.directive_line(lexer) else {
+                continue;
+            };
+            let taken = self.conditions.last().is_some_and(|c| c.taken);
+            match name.text.as_str() {
+                "if" | "ifdef" | "ifndef" => depth += 1,
+                "endif" if depth > 0 => depth -= 1,
+                "endif" => {
+                    self.conditions.pop();
+                    return;
+                }
+                // skipped after the error
+                "else" | "elif" if depth == 0 && self.check_else(&name) => {}
+                "else" if depth == 0 && !taken => break,
+                "elif" if depth == 0 && !taken => {
+                    let Some(args) = self.tokenize(rest, offset) else {
+                        continue;
+                    };
+                    let eof = offset + rest.len()..offset + rest.len();
+                    if self.condition(args, eof) {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(condition) = self.conditions.last_mut() {
+            condition.taken = true;
+        }
+    }
+
+    // '#elif' or '#else' after '#else' is an error, and the group is skipped.
+    // Returns whether it is.
+    fn check_else(&mut self, name: &Token) -> bool {
+        let Some(condition) = self.conditions.last_mut() else {
+            return false;
+        };
+        let after_else = condition.seen_else;
+        condition.seen_else |= name.text == "else";
+        if after_else {
+            let error = PreprocessError::AfterElse(name.text.clone());
+            self.errors.push(preprocess_error(error, name.span.clone()));
+        }
+        after_else
+    }
+
+    // The value of the expression of '#if' or '#elif' is not 0
+    fn condition(&mut self, args: Vec<Token>, eof: Range<usize>) -> bool {
+        // "defined X" and "defined(X)" are replaced before expansion
+        let mut tokens = vec![];
+        let mut args = args.into_iter();
+        while let Some(token) = args.next() {
+            if token.text != "defined" {
+                tokens.push(PpToken::new(token));
+                continue;
+            }
+            let mut name = args.next();
+            let paren = name
+                .as_ref()
+                .is_some_and(|name| name.kind == TokenKind::Sep(Separator::RoundBracketL));
+            if paren {
+                name = args.next();
+            }
+            let rparen = TokenKind::Sep(Separator::RoundBracketR);
+            match name {
+                Some(name)
+                    if is_macro_name(&name)
+                        && (!paren || args.next().is_some_and(|token| token.kind == rparen)) =>
+                {
+                    let defined = self.macros.contains_key(&name.text) as i64;
+                    tokens.push(PpToken::new(number(defined, token.span)));
+                }
+                _ => {
+                    self.errors
+                        .push(preprocess_error(PreprocessError::NoMacroName, token.span));
+                    return false;
+                }
+            }
+        }
+        // identifiers left after expansion are 0
+        let tokens = self
+            .expand_list(tokens)
+            .into_iter()
+            .map(|token| match token.token {
+                token if is_macro_name(&token) => number(0, token.span),
+                token => intmax(token),
+            })
+            .collect();
+        match parser::eval_condition(tokens, eof) {
+            Ok(value) => value != 0,
+            Err(error) => {
+                self.errors.push(error);
+                false
+            }
+        }
+    }
+
+    // "#define name body" or "#define name(params) body"
+    fn define_macro(&mut self, directive: &Token, args: Vec<Token>) {
+        let error = |error, span| preprocess_error(error, span);
+        let mut args = args.into_iter().peekable();
+        let name = match args.next() {
+            Some(token) if is_macro_name(&token) => token,
+            _ => {
+                let span = directive.span.clone();
+                return self.errors.push(error(PreprocessError::NoMacroName, span));
+            }
+        };
+        // the parameters follow the name without spaces
+        let lparen = args.peek().is_some_and(|token| {
+            token.kind == TokenKind::Sep(Separator::RoundBracketL)
+                && token.span.start == name.span.end
+        });
+        let mut params = None;
+        let mut variadic = false;
+        if lparen {
+            args.next();
+            match macro_params(&mut args) {
+                Ok((names, is_variadic)) => {
+                    params = Some(names);
+                    variadic = is_variadic;
+                }
+                Err(span) => {
+                    let span = span.unwrap_or(name.span);
+                    return self
+                        .errors
+                        .push(error(PreprocessError::InvalidParams, span));
+                }
+            }
+        }
+        let body: Vec<Token> = args.collect();
+        let edges = [body.first(), body.last()];
+        if let Some(token) = edges
+            .into_iter()
+            .flatten()
+            .find(|token| token.kind == TokenKind::HashHash)
+        {
+            let span = token.span.clone();
+            return self.errors.push(error(PreprocessError::PasteAtEdge, span));
+        }
+        if let Some(names) = &params {
+            let not_param = body
+                .iter()
+                .zip(body.iter().skip(1).map(Some).chain([None]))
+                .find(|(token, next)| {
+                    token.kind == TokenKind::Hash
+                        && !next.is_some_and(|next| names.contains(&next.text))
+                });
+            if let Some((token, _)) = not_param {
+                let span = token.span.clone();
+                return self.errors.push(error(PreprocessError::NotParam, span));
+            }
+        }
+        self.macros.insert(
+            name.text,
+            Macro {
+                params,
+                variadic,
+                body,
+            },
+        );
+    }
+
+    // #include "name" searches the directory of the current file, then the include paths.
+    // #include <name> searches only the include paths.
+    fn include(&mut self, path: &Path, rest: &str, span: Range<usize>, output: &mut Vec<PpToken>) {
+        let leading = rest.len() - rest.trim_start().len();
+        let rest = rest.trim();
+        let span = span.start + leading..span.start + leading + rest.len();
+        let target = match rest.chars().next() {
+            Some('"') => rest[1..].split_once('"').map(|(name, _)| (name, true)),
+            Some('<') => rest[1..].split_once('>').map(|(name, _)| (name, false)),
+            _ => None,
+        };
+        let Some((name, quoted)) = target else {
+            self.errors
+                .push(preprocess_error(PreprocessError::ExpectedFilename, span));
+            return;
+        };
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let found = quoted
+            .then(|| dir.join(name))
+            .into_iter()
+            .chain(self.include_paths.iter().map(|include| include.join(name)))
+            .find(|file| file.is_file());
+        let Some(file) = found else {
+            let error = PreprocessError::FileNotFound(name.to_string());
+            self.errors.push(preprocess_error(error, span));
+            return;
+        };
+        if self.once.contains(&canonical(&file)) {
+            return;
+        }
+        if self.depth == MAX_INCLUDE_DEPTH {
+            self.errors
+                .push(preprocess_error(PreprocessError::IncludeTooDeep, span));
+            return;
+        }
+        let Ok(src) = fs::read_to_string(&file) else {
+            let error = PreprocessError::CannotRead(file.to_string_lossy().into_owned());
+            self.errors.push(preprocess_error(error, span));
+            return;
+        };
+        self.depth += 1;
+        self.file(&file, &src, output);
+        self.depth -= 1;
+    }
+}
+
+// the same file may be included by different paths
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[test]
+fn test_object_like_macro() {
+    let src = "#define N 3\n#define M N + N\nM;\n#undef N\nN";
+    let tokens = Preprocessor::default().run("a.c", src).unwrap();
+    let texts: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+    assert_eq!(texts, vec!["3", "+", "3", ";", "N"]);
+    // expanded tokens are placed at the macro
+    assert_eq!(tokens[0].span, 28..29);
+}
+
+#[test]
+fn test_recursive_macro() {
+    let src = "#define A B\n#define B A\nA";
+    let tokens = Preprocessor::default().run("a.c", src).unwrap();
+    assert_eq!(tokens[0].text, "A");
+}
+
+#[test]
+fn test_hash_inside_line() {
+    let tokens = Preprocessor::default().run("a.c", "a # define").unwrap();
+    assert_eq!(tokens[1].kind, TokenKind::Hash);
+}
+
+#[test]
+fn test_directive_errors() {
+    let errors = Preprocessor::default()
+        .run(
+            "a.c",
+            "#foo\n#define 1\n#include foo.h\n#include \"no such file.h\"",
+        )
+        .unwrap_err();
+    let error_types: Vec<CompileErrorType> = errors.into_iter().map(|e| e.error_type).collect();
+    assert_eq!(
+        error_types,
+        vec![
+            CompileErrorType::Preprocessing(PreprocessError::InvalidDirective),
+            CompileErrorType::Preprocessing(PreprocessError::NoMacroName),
+            CompileErrorType::Preprocessing(PreprocessError::ExpectedFilename),
+            CompileErrorType::Preprocessing(PreprocessError::FileNotFound(
+                "no such file.h".to_string()
+            )),
+        ]
+    );
+}
+
+#[test]
+fn test_directive_comments() {
+    // a comment ends the directive only with its last line
+    let src = "#define X 1 /* a\n b */\n#define S \"/*\" // c */\n#if X /*\n*/ == 1\nX S\n#endif";
+    assert_eq!(expand_texts(src), vec!["1", "\"/*\""]);
+}
+
+#[test]
+fn test_source_map() {
+    let mut sources = SourceMap::default();
+    assert_eq!(sources.add("a.c", "abc"), 0);
+    assert_eq!(sources.add("b.h", "de"), 4);
+    let origin = |pos| {
+        sources
+            .origin(pos)
+            .map(|(file, offset)| (file.name.as_str(), offset))
+    };
+    assert_eq!(origin(3), Some(("a.c", 3)));
+    assert_eq!(origin(5), Some(("b.h", 1)));
+}
+
+#[test]
+fn test_include() {
+    let dir = std::env::temp_dir().join(format!("r9cc-include-{}", std::process::id()));
+    fs::create_dir_all(dir.join("inc")).unwrap();
+    fs::write(dir.join("inc/a.h"), "#pragma once\n#define A 1\nint a;").unwrap();
+    fs::write(dir.join("b.h"), "#include <a.h>\nA +;").unwrap();
+    let main = dir.join("main.c");
+    let src = "#include \"b.h\" /* a comment\n of two lines */\n#include \"inc/a.h\"\nA";
+    let mut preprocessor = Preprocessor::new(vec![dir.join("inc")]);
+    let tokens = preprocessor.run(&main.to_string_lossy(), src).unwrap();
+    let texts: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+    // a.h is included once
+    assert_eq!(texts, vec!["int", "a", ";", "1", "+", ";", "1"]);
+    // the span of '+' is in b.h
+    let (file, offset) = preprocessor.sources().origin(tokens[4].span.start).unwrap();
+    assert!(file.name.ends_with("b.h"));
+    assert_eq!(offset, 17);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(test)]
+fn expand_texts(src: &str) -> Vec<String> {
+    let tokens = Preprocessor::default().run("a.c", src).unwrap();
+    tokens.into_iter().map(|token| token.text).collect()
+}
+
+#[test]
+fn test_function_like_macro() {
+    let src = "#define MAX(a, b) ((a) > (b) ? (a) : (b))\n#define F (1)\nMAX(F, g(2, 3)) MAX";
+    assert_eq!(
+        expand_texts(src).join(" "),
+        "( ( ( 1 ) ) > ( g ( 2 , 3 ) ) ? ( ( 1 ) ) : ( g ( 2 , 3 ) ) ) MAX"
+    );
+    // a space between the name and '(' makes an object-like macro
+    assert_eq!(
+        expand_texts("#define G (x) x\nG(1)").join(" "),
+        "( x ) x ( 1 )"
+    );
+}
+
+#[test]
+fn test_stringize_and_paste() {
+    let src = "#define STR(x) #x\n#define CAT(a, b) a ## b\n#define N 1\n\
+               STR(a  + \"b\") CAT(x, N) CAT(, y) CAT(<, <=)";
+    assert_eq!(expand_texts(src), vec![r#""a + \"b\"""#, "xN", "y", "<<="]);
+    // arguments of '#' are not expanded, unless they are passed through another macro
+    let src = "#define str(s) #s\n#define xstr(s) str(s)\n#define foo 4\nstr(foo) xstr(foo)";
+    assert_eq!(expand_texts(src), vec!["\"foo\"", "\"4\""]);
+    // '##' is also pasting in object-like macros, where '#' is an ordinary token
+    let src = "#define H A ## B\n#define AB 2\n#define S # x\nH S";
+    assert_eq!(expand_texts(src), vec!["2", "#", "x"]);
+}
+
+#[test]
+fn test_variadic_macro() {
+    let src =
+        "#define F(fmt, ...) f(fmt, __VA_ARGS__)\n#define G(...) g(__VA_ARGS__)\nF(a, b, c) G()";
+    assert_eq!(expand_texts(src).join(" "), "f ( a , b , c ) g ( )");
+}
+
+#[test]
+fn test_hideset() {
+    // each macro is expanded once in its own expansion
+    let src = "#define f(x) x f\n#define g f\nf(1)(2) g(3)";
+    assert_eq!(expand_texts(src).join(" "), "1 f ( 2 ) 3 f");
+    let src = "#define x 2 * x\n#define y(a) a + y(a)\nx y(x)";
+    assert_eq!(expand_texts(src).join(" "), "2 * x 2 * x + y ( 2 * x )");
+}
+
+#[test]
+fn test_conditional() {
+    let src = "#define A 2\n\
+               #if A == 2 && defined(A) && !defined B\na\n#else\nb\n#endif\n\
+               #if 0\n#if 1\nc\n#endif\n'unterminated\n#elif A - 2\nd\n#elif UNDEFINED + 1\ne\n#else\nf\n#endif\n\
+               #ifdef A\ng\n#endif\n#ifndef A\nh\n#else\ni\n#endif";
+    assert_eq!(expand_texts(src), vec!["a", "e", "g", "i"]);
+    // unsigned integers, with the suffix 'u' or too large for intmax_t, convert the other operand
+    let src = "#if 0xffffffffffffffff > 0 && -1 > 0u && -1 < 0 && 0xffffffff > 0\na\n#endif\n\
+               #if -1 / 2u == 0x7fffffffffffffff && (-1u >> 63) == 1 && 'u' == 117\nb\n#endif\n\
+               #if 0 && 1 / 0 || 1 ? 2 : 1 / 0\nc\n#endif";
+    assert_eq!(expand_texts(src), vec!["a", "b", "c"]);
+}
+
+#[test]
+fn test_builtin_macros() {
+    let src = "__COUNTER__ __COUNTER__\n#define L __LINE__\nL __FILE__";
+    assert_eq!(expand_texts(src), vec!["0", "1", "3", "\"a.c\""]);
+}
+
+#[test]
+fn test_macro_errors() {
+    let error_types = |src| -> Vec<CompileErrorType> {
+        let errors = Preprocessor::default().run("a.c", src).unwrap_err();
+        errors.into_iter().map(|e| e.error_type).collect()
+    };
+    assert_eq!(
+        error_types("#error stop here\n#if 1\n#endif\n#endif\n#if 1"),
+        vec![
+            CompileErrorType::Preprocessing(PreprocessError::Error("stop here".to_string())),
+            CompileErrorType::Preprocessing(PreprocessError::Unmatched("endif".to_string())),
+            CompileErrorType::Preprocessing(PreprocessError::UnterminatedCondition),
+        ]
+    );
+    assert_eq!(
+        error_types("#if 1\n#else\n#else\n#elif 1\n#endif\n#if 0\n#else\n#elif 1\n#else\n#endif"),
+        vec![
+            CompileErrorType::Preprocessing(PreprocessError::AfterElse("else".to_string())),
+            CompileErrorType::Preprocessing(PreprocessError::AfterElse("elif".to_string())),
+            CompileErrorType::Preprocessing(PreprocessError::AfterElse("elif".to_string())),
+            CompileErrorType::Preprocessing(PreprocessError::AfterElse("else".to_string())),
+        ]
+    );
+    let messages = |src| -> Vec<String> {
+        let errors = Preprocessor::default().run("a.c", src).unwrap_err();
+        errors
+            .into_iter()
+            .map(|e| e.error_type.to_string())
+            .collect()
+    };
+    assert_eq!(
+        messages(
+            "#if 1 / 0\n#endif\n#if 2 % (1 - 1)\n#endif\n#if x = 1\n#endif\n#if \"s\"\n#endif"
+        ),
+        vec![
+            "division by zero in #if",
+            "division by zero in #if",
+            "invalid expression in #if",
+            "invalid expression in #if",
+        ]
+    );
+    assert_eq!(
+        messages("#if 0x7fffffffffffffff + 1\n#endif\n#if 1 << 64\n#endif"),
+        vec!["integer overflow in #if", "integer overflow in #if"]
+    );
+    assert_eq!(
+        error_types("#define F(a) a\nF(1, 2) F(3"),
+        vec![
+            CompileErrorType::Preprocessing(PreprocessError::WrongArgCount("F".to_string())),
+            CompileErrorType::Preprocessing(PreprocessError::UnterminatedArgs("F".to_string())),
+        ]
+    );
+    assert_eq!(
+        error_types(
+            "#define F(a, 1) a\n#define G(a) #b\n#define H ## a\n#define C(a) a ## +\nC(-)"
+        ),
+        vec![
+            CompileErrorType::Preprocessing(PreprocessError::InvalidParams),
+            CompileErrorType::Preprocessing(PreprocessError::NotParam),
+            CompileErrorType::Preprocessing(PreprocessError::PasteAtEdge),
+            CompileErrorType::Preprocessing(PreprocessError::InvalidPaste("-+".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn test_command_line_macros() {
+    let mut preprocessor = Preprocessor::default();
+    preprocessor.define("N=3");
+    preprocessor.define("F(x)=-x");
+    preprocessor.define("DEBUG");
+    preprocessor.define("M=1");
+    preprocessor.undefine("M");
+    let tokens = preprocessor.run("a.c", "N F(2) DEBUG M").unwrap();
+    let texts: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+    assert_eq!(texts, vec!["3", "-", "2", "1", "M"]);
+    // spans of the main file are not shifted by the command line
+    assert_eq!(tokens[4].span, 13..14);
+}
+
+#[test]
+fn test_preprocessed() {
+    let mut preprocessor = Preprocessor::default();
+    let src = "#define ONE 1\nint  a = ONE;\n\n#if 0\n\n\n\n\n\n\n\n\n#endif\n  int b;";
+    let tokens = preprocessor.run("a.c", src).unwrap();
+    assert_eq!(
+        preprocessor.sources().preprocessed(&tokens),
+        "# 2 \"a.c\"\nint a = 1;\n# 14 \"a.c\"\n  int b;\n"
+    ); // tokens which would be read as another token are separated
+    let src = "#define M -\n#define P +\n-M x+P+1 a+++b";
+    let tokens = preprocessor.run("b.c", src).unwrap();
+    assert_eq!(
+        preprocessor.sources().preprocessed(&tokens),
+        "# 3 \"b.c\"\n- - x+ + +1 a+++b\n"
+    );
+}
+
+#[test]
+fn test_line_markers() {
+    // the output of -E is read again
+    let src = "# 1 \"a.c\"\n#line 20\n#line 3 \"b.h\"\n# 5 \"c.h\" 1 3\nint x;";
+    let tokens = Preprocessor::default().run("a.c", src).unwrap();
+    assert_eq!(tokens.len(), 3);
+    let errors = Preprocessor::default()
+        .run("a.c", "#line\n#line x\n#line 3 \"b.h\" 1\n# 2 x")
+        .unwrap_err();
+    assert_eq!(errors.len(), 4);
+    assert!(errors
+        .iter()
+        .all(|error| error.error_type
+            == CompileErrorType::Preprocessing(PreprocessError::InvalidLine)));
+    // the lines after them are counted from the line and in the file they name
+    let mut preprocessor = Preprocessor::default();
+    let src = "#line 20\n__LINE__\n# 5 \"b.h\" 1\n__LINE__ __FILE__\n\nx";
+    let tokens = preprocessor.run("a.c", src).unwrap();
+    assert_eq!(
+        preprocessor.sources().preprocessed(&tokens),
+        "# 20 \"a.c\"\n20\n# 5 \"b.h\"\n5 \"b.h\"\n\nx\n"
+    );
+    let error = preprocess_error(PreprocessError::NoMacroName, tokens[3].span.clone());
+    assert_eq!(
+        preprocessor.sources().render(&error),
+        "b.h:7:1: error: macro name must be an identifier\n 7 | x\n   | ^"
+    );
+}
@@ -6,16 +6,26 @@ use std::ops::Range;
 pub struct RawStream<'a> {
     src: &'a str,
     pos: usize,
+    line_start: bool, // the last token is the first one of its line
 }
 
 #[derive(Debug, PartialEq)]
-pub struct RawTokens<'a> {
-    raw_tokens: Vec<Token<'a>>,
+pub struct RawTokens {
+    raw_tokens: Vec<Token>,
     index: usize,
 }
 
-impl<'a> Iterator for RawTokens<'a> {
-    type Item = Token<'a>;
+impl RawTokens {
+    pub fn new(raw_tokens: Vec<Token>) -> Self {
+        RawTokens {
+            raw_tokens,
+            index: 0,
+        }
+    }
+}
+
+impl Iterator for RawTokens {
+    type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.index += 1;
@@ -27,30 +37,75 @@ impl<'a> Iterator for RawTokens<'a> {
     }
 }
 
-pub type Tokens<'a> = Peekable<RawTokens<'a>>;
+pub type Tokens = Peekable<RawTokens>;
+
+// Symbols made of punctuation characters, longest first
+const PUNCTUATORS: [&str; 46] = [
+    "...", "<<=", ">>=", "==", "!=", "<=", ">=", "<<", ">>", "&&", "||", "++", "--", "+=", "-=",
+    "*=", "/=", "%=", "&=", "|=", "^=", "##", "+", "-", "*", "/", "%", "&", "|", "^", "~", "!",
+    "<", ">", "=", "?", ":", "(", ")", "{", "}", "[", "]", ";", ",", "#",
+];
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Token<'a> {
-    pub text: &'a str,
+pub struct Token {
+    pub text: String,
     pub kind: TokenKind,
-    pub span: Range<usize>, // Token place in Tokens
+    pub span: Range<usize>, // Token place in the source map, which tells its file and offset
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Separator {
-    RoundBracketL, // '('
-    RoundBracketR, // ')'
-    SemiColon,     // ';'
+    RoundBracketL,  // '('
+    RoundBracketR,  // ')'
+    CurlyBracketL,  // '{'
+    CurlyBracketR,  // '}'
+    SquareBracketL, // '['
+    SquareBracketR, // ']'
+    SemiColon,      // ';'
+    Comma,          // ','
+}
+
+// C type of an integer literal, decided by its value, base and suffix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumberType {
+    Int,
+    UInt,  // 'u'
+    Long,  // 'l', 'll', or too large for int
+    ULong, // 'ul', 'ull', or too large for long
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TokenKind {
-    Ident, // identifier
-    Number(i64),
+    Ident,                   // identifier
+    Number(i64, NumberType), // unsigned values are stored as their bit pattern
     Add,
     Sub,
     Mul,
     Div,
+    Amp,       // '&'
+    Mod,       // '%'
+    Not,       // '!'
+    Tilde,     // '~'
+    Pipe,      // '|'
+    Caret,     // '^'
+    Shl,       // '<<'
+    Shr,       // '>>'
+    AndAnd,    // '&&'
+    OrOr,      // '||'
+    Inc,       // '++'
+    Dec,       // '--'
+    Question,  // '?'
+    Colon,     // ':'
+    AddAssign, // '+='
+    SubAssign, // '-='
+    MulAssign, // '*='
+    DivAssign, // '/='
+    ModAssign, // '%='
+    ShlAssign, // '<<='
+    ShrAssign, // '>>='
+    AndAssign, // '&='
+    OrAssign,  // '|='
+    XorAssign, // '^='
     Eq,        // '=='
     NotEq,     // '!='
     Less,      // '<'
@@ -59,12 +114,79 @@ pub enum TokenKind {
     GreaterEq, // '>='
     Assign,    // '='
     Return,    // 'return'
+    If,        // 'if'
+    Else,      // 'else'
+    While,     // 'while'
+    For,       // 'for'
+    Break,     // 'break'
+    Continue,  // 'continue'
+    Int,       // 'int'
+    Char,      // 'char'
+    Str,       // string literal, unescaped by `unescape`
+    Sizeof,    // 'sizeof'
+    Hash,      // '#', only valid in preprocessing directives
+    HashHash,  // '##', only valid in macro definitions
+    Ellipsis,  // '...', the variadic parameter of a macro
     Sep(Separator),
 }
 
 impl<'a> RawStream<'a> {
     pub fn new(src: &'a str) -> Self {
-        RawStream { src, pos: 0 }
+        RawStream {
+            src,
+            pos: 0,
+            line_start: true,
+        }
+    }
+
+    // Whether the last token was the first one of its line
+    pub fn line_start(&self) -> bool {
+        self.line_start
+    }
+
+    // Skip the rest of the line including backslash-newlines and comments, and return it
+    pub fn take_line(&mut self) -> (&'a str, Range<usize>) {
+        let start = self.pos;
+        while let Some(c) = self.advance() {
+            match c {
+                '\\' if self.peek() == Some('\n') => {
+                    self.advance();
+                }
+                '\n' => {
+                    self.pos -= 1;
+                    break;
+                }
+                '/' if self.peek() == Some('/') => {
+                    while self.peek().is_some_and(|next| next != '\n') {
+                        self.advance();
+                    }
+                }
+                // a comment is a space, so its newlines do not end the line
+                '/' if self.peek() == Some('*') => {
+                    self.advance();
+                    match self.rest().find("*/") {
+                        Some(end) => self.pos += end + 2,
+                        None => self.pos = self.src.len(),
+                    }
+                }
+                // "/*" in literals is not a comment
+                '"' | '\'' => {
+                    while let Some(next) = self.peek() {
+                        if next == '\n' {
+                            break;
+                        }
+                        self.advance();
+                        if next == '\\' {
+                            self.advance();
+                        } else if next == c {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        (&self.src[start..self.pos], start..self.pos)
     }
 
     fn rest(&self) -> &'a str {
@@ -107,18 +229,26 @@ impl<'a> RawStream<'a> {
             None
         }
     }
-    fn tokenize_number(&mut self) -> Token<'a> {
+
+    // decimal, hex "0x", binary "0b" or octal "0", followed by an optional suffix
+    fn tokenize_number(&mut self) -> Result<Token, CompileError> {
         let (text, span) = self
-            .take_while(|c| matches!(c, '0'..='9'))
+            .take_while(|c| c.is_ascii_alphanumeric() || c == '_')
             .expect("Error: No digit.");
-        Token {
-            text,
-            kind: TokenKind::Number(text.parse().expect("Number parse failed.")),
-            span,
+        match parse_number(text) {
+            Ok((num, ty)) => Ok(Token {
+                text: text.to_string(),
+                kind: TokenKind::Number(num, ty),
+                span,
+            }),
+            Err(error) => Err(CompileError {
+                error_type: CompileErrorType::Tokenizing(error),
+                pos: Some(span),
+            }),
         }
     }
 
-    fn tokenize_reserved(&mut self, symbol: &'a str) -> Token<'a> {
+    fn tokenize_reserved(&mut self, symbol: &'a str) -> Token {
         let start = self.pos;
         for _ in 0..symbol.len() {
             self.advance();
@@ -129,9 +259,38 @@ impl<'a> RawStream<'a> {
             "-" => TokenKind::Sub,
             "*" => TokenKind::Mul,
             "/" => TokenKind::Div,
+            "&" => TokenKind::Amp,
+            "%" => TokenKind::Mod,
+            "!" => TokenKind::Not,
+            "~" => TokenKind::Tilde,
+            "|" => TokenKind::Pipe,
+            "^" => TokenKind::Caret,
+            "<<" => TokenKind::Shl,
+            ">>" => TokenKind::Shr,
+            "&&" => TokenKind::AndAnd,
+            "||" => TokenKind::OrOr,
+            "++" => TokenKind::Inc,
+            "--" => TokenKind::Dec,
+            "?" => TokenKind::Question,
+            ":" => TokenKind::Colon,
+            "+=" => TokenKind::AddAssign,
+            "-=" => TokenKind::SubAssign,
+            "*=" => TokenKind::MulAssign,
+            "/=" => TokenKind::DivAssign,
+            "%=" => TokenKind::ModAssign,
+            "<<=" => TokenKind::ShlAssign,
+            ">>=" => TokenKind::ShrAssign,
+            "&=" => TokenKind::AndAssign,
+            "|=" => TokenKind::OrAssign,
+            "^=" => TokenKind::XorAssign,
             "(" => TokenKind::Sep(Separator::RoundBracketL),
             ")" => TokenKind::Sep(Separator::RoundBracketR),
+            "{" => TokenKind::Sep(Separator::CurlyBracketL),
+            "}" => TokenKind::Sep(Separator::CurlyBracketR),
+            "[" => TokenKind::Sep(Separator::SquareBracketL),
+            "]" => TokenKind::Sep(Separator::SquareBracketR),
             ";" => TokenKind::Sep(Separator::SemiColon),
+            "," => TokenKind::Sep(Separator::Comma),
             "==" => TokenKind::Eq,
             "!=" => TokenKind::NotEq,
             "<" => TokenKind::Less,
@@ -139,93 +298,291 @@ impl<'a> RawStream<'a> {
             ">" => TokenKind::Greater,
             ">=" => TokenKind::GreaterEq,
             "=" => TokenKind::Assign,
+            "#" => TokenKind::Hash,
+            "##" => TokenKind::HashHash,
+            "..." => TokenKind::Ellipsis,
             _ => unreachable!(), // reservedは確定しているのでunreachable
         };
         Token {
-            text: symbol,
+            text: symbol.to_string(),
             kind,
             span: start..end,
         }
     }
 
-    fn tokenize_term(&mut self) -> Token<'a> {
+    fn tokenize_term(&mut self) -> Token {
         let (text, span) = self
             .take_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_'))
             .expect("Error: identifier is alphabetical");
-        if text == "return" {
-            return Token {
-                text,
-                kind: TokenKind::Return,
-                span,
-            };
-        }
+        let kind = match text {
+            "return" => TokenKind::Return,
+            "if" => TokenKind::If,
+            "else" => TokenKind::Else,
+            "while" => TokenKind::While,
+            "for" => TokenKind::For,
+            "break" => TokenKind::Break,
+            "continue" => TokenKind::Continue,
+            "int" => TokenKind::Int,
+            "char" => TokenKind::Char,
+            "sizeof" => TokenKind::Sizeof,
+            _ => TokenKind::Ident,
+        };
         Token {
-            text,
-            kind: TokenKind::Ident,
+            text: text.to_string(),
+            kind,
             span,
         }
     }
 
+    // Read a quoted literal and return its text and span, including the quotes
+    fn take_quoted(&mut self, quote: char) -> Option<(&'a str, Range<usize>)> {
+        let start = self.pos;
+        self.advance(); // opening quote
+        loop {
+            match self.peek() {
+                // literals cannot span lines
+                None | Some('\n') => return None,
+                Some('\\') => {
+                    self.advance();
+                    if self.peek() != Some('\n') {
+                        self.advance();
+                    }
+                }
+                Some(c) => {
+                    self.advance();
+                    if c == quote {
+                        break;
+                    }
+                }
+            }
+        }
+        Some((&self.src[start..self.pos], start..self.pos))
+    }
+
+    fn tokenize_string(&mut self) -> Result<Token, CompileError> {
+        let start = self.pos;
+        let Some((text, span)) = self.take_quoted('"') else {
+            return Err(CompileError {
+                error_type: CompileErrorType::Tokenizing(TokenizeError::UnterminatedString),
+                pos: Some(start..self.pos),
+            });
+        };
+        if unescape(&text[1..text.len() - 1]).is_none() {
+            return Err(CompileError {
+                error_type: CompileErrorType::Tokenizing(TokenizeError::InvalidEscape),
+                pos: Some(span),
+            });
+        }
+        Ok(Token {
+            text: text.to_string(),
+            kind: TokenKind::Str,
+            span,
+        })
+    }
+
+    // A character literal is an int, as in C
+    fn tokenize_char(&mut self) -> Result<Token, CompileError> {
+        let start = self.pos;
+        let Some((text, span)) = self.take_quoted('\'') else {
+            return Err(CompileError {
+                error_type: CompileErrorType::Tokenizing(TokenizeError::UnterminatedChar),
+                pos: Some(start..self.pos),
+            });
+        };
+        let error = match unescape(&text[1..text.len() - 1]).as_deref() {
+            // char is signed
+            Some(&[c]) => {
+                return Ok(Token {
+                    text: text.to_string(),
+                    kind: TokenKind::Number(c as i8 as i64, NumberType::Int),
+                    span,
+                })
+            }
+            Some(_) => TokenizeError::InvalidChar,
+            None => TokenizeError::InvalidEscape,
+        };
+        Err(CompileError {
+            error_type: CompileErrorType::Tokenizing(error),
+            pos: Some(span),
+        })
+    }
+
     fn tokenize_unknown(&mut self) -> CompileError {
         // read until space
         let (text, span) = self
             .take_while(|c| !c.is_ascii_whitespace())
             .expect("Error: whitespace only.");
         CompileError {
-            error_type: CompileErrorType::Tokenizing(TokenizeError(text.to_string())),
+            error_type: CompileErrorType::Tokenizing(TokenizeError::Unknown(text.to_string())),
             pos: Some(span),
         }
     }
+}
 
-    // raise tokenize error
-    pub fn check(&mut self) -> Result<RawTokens, Vec<CompileError>> {
-        let (tokens, errors): (Vec<_>, Vec<_>) = self.into_iter().partition(Result::is_ok);
-        let tokens: Vec<Token> = tokens.into_iter().map(Result::unwrap).collect();
-        let errors: Vec<CompileError> = errors.into_iter().map(Result::unwrap_err).collect();
-        if !errors.is_empty() {
-            Err(errors)
-        } else {
-            Ok(RawTokens {
-                raw_tokens: tokens,
-                index: 0,
-            })
+// Value and type of an integer literal (C11 6.4.4.1)
+fn parse_number(text: &str) -> Result<(i64, NumberType), TokenizeError> {
+    let lower = text.to_ascii_lowercase();
+    let (radix, body) = if let Some(rest) = lower.strip_prefix("0x") {
+        (16, rest)
+    } else if let Some(rest) = lower.strip_prefix("0b") {
+        (2, rest)
+    } else if let Some(rest) = lower.strip_prefix('0') {
+        (8, rest)
+    } else {
+        (10, &lower[..])
+    };
+    let end = body
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(body.len());
+    let (digits, suffix) = body.split_at(end);
+    // "0" itself is an octal literal without digits
+    if digits.is_empty() && radix != 8 {
+        return Err(TokenizeError::InvalidNumber);
+    }
+    let (unsigned, long) = match suffix {
+        "" => (false, false),
+        "u" => (true, false),
+        "l" | "ll" => (false, true),
+        "ul" | "lu" | "ull" | "llu" => (true, true),
+        _ => return Err(TokenizeError::InvalidNumber),
+    };
+    // "ll" and "LL" are allowed, but not "lL"
+    if text.contains("lL") || text.contains("Ll") {
+        return Err(TokenizeError::InvalidNumber);
+    }
+    let num = if digits.is_empty() {
+        0
+    } else {
+        u64::from_str_radix(digits, radix).map_err(|_| TokenizeError::NumberOutOfRange)?
+    };
+
+    // the first type which can represent the value
+    let decimal = radix == 10;
+    let ty = match (unsigned, long) {
+        (false, false) if num <= i32::MAX as u64 => NumberType::Int,
+        (false, false) if !decimal && num <= u32::MAX as u64 => NumberType::UInt,
+        (false, _) if num <= i64::MAX as u64 => NumberType::Long,
+        (false, _) if !decimal => NumberType::ULong,
+        (false, _) => return Err(TokenizeError::NumberOutOfRange),
+        (true, false) if num <= u32::MAX as u64 => NumberType::UInt,
+        (true, _) => NumberType::ULong,
+    };
+    Ok((num as i64, ty))
+}
+
+// Convert the escape sequences in the body of a literal to bytes
+// None if an escape sequence is invalid
+pub fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
         }
+        let c = chars.next()?;
+        let byte = match c {
+            'a' => 0x07,
+            'b' => 0x08,
+            't' => b'\t',
+            'n' => b'\n',
+            'v' => 0x0b,
+            'f' => 0x0c,
+            'r' => b'\r',
+            'e' => 0x1b, // GNU extension
+            // octal: up to 3 digits
+            '0'..='7' => {
+                let mut value = c.to_digit(8)?;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                u8::try_from(value).ok()?
+            }
+            // hexadecimal: any number of digits
+            'x' => {
+                let mut value: u32 = 0;
+                let mut digits = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(16)) {
+                    value = value.checked_mul(16)? + digit;
+                    digits += 1;
+                    chars.next();
+                }
+                if digits == 0 {
+                    return None;
+                }
+                u8::try_from(value).ok()?
+            }
+            // '\\', '\'', '\"', '\?' and unknown escapes are the character itself
+            c => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+        };
+        bytes.push(byte);
     }
+    Some(bytes)
 }
 
 impl<'a> Iterator for RawStream<'a> {
-    type Item = Result<Token<'a>, CompileError>;
+    type Item = Result<Token, CompileError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // ignore spaces
-        while let Some(x) = self.peek() {
-            if x.is_ascii_whitespace() {
-                self.advance();
-            } else {
-                break;
+        // ignore spaces, comments and backslash-newlines
+        self.line_start = self.pos == 0;
+        loop {
+            match self.peek2() {
+                (Some('\n'), _) => {
+                    self.line_start = true;
+                    self.advance();
+                }
+                (Some(x), _) if x.is_ascii_whitespace() => {
+                    self.advance();
+                }
+                (Some('\\'), Some('\n')) => {
+                    self.pos += 2;
+                }
+                (Some('/'), Some('/')) => {
+                    self.take_while(|c| c != '\n');
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.pos;
+                    match self.rest()[2..].find("*/") {
+                        Some(len) => self.pos += 2 + len + 2,
+                        None => {
+                            self.pos = self.src.len();
+                            return Some(Err(CompileError {
+                                error_type: CompileErrorType::Tokenizing(
+                                    TokenizeError::UnterminatedComment,
+                                ),
+                                pos: Some(start..self.pos),
+                            }));
+                        }
+                    }
+                }
+                _ => break,
             }
         }
-        return match self.peek()? {
-            '+' => Some(Ok(self.tokenize_reserved("+"))),
-            '-' => Some(Ok(self.tokenize_reserved("-"))),
-            '*' => Some(Ok(self.tokenize_reserved("*"))),
-            '/' => Some(Ok(self.tokenize_reserved("/"))),
-            '(' => Some(Ok(self.tokenize_reserved("("))),
-            ')' => Some(Ok(self.tokenize_reserved(")"))),
-            ';' => Some(Ok(self.tokenize_reserved(";"))),
-            '0'..='9' => Some(Ok(self.tokenize_number())),
+        match self.peek()? {
+            '0'..='9' => Some(self.tokenize_number()),
+            '"' => Some(self.tokenize_string()),
+            '\'' => Some(self.tokenize_char()),
             'a'..='z' | 'A'..='Z' | '_' => Some(Ok(self.tokenize_term())),
-            _ => match self.peek2() {
-                (Some('='), Some('=')) => Some(Ok(self.tokenize_reserved("=="))),
-                (Some('!'), Some('=')) => Some(Ok(self.tokenize_reserved("!="))),
-                (Some('<'), Some('=')) => Some(Ok(self.tokenize_reserved("<="))),
-                (Some('>'), Some('=')) => Some(Ok(self.tokenize_reserved(">="))),
-                (Some('<'), _) => Some(Ok(self.tokenize_reserved("<"))),
-                (Some('>'), _) => Some(Ok(self.tokenize_reserved(">"))),
-                (Some('='), _) => Some(Ok(self.tokenize_reserved("="))),
-                _ => Some(Err(self.tokenize_unknown())),
+            _ => match PUNCTUATORS
+                .iter()
+                .find(|symbol| self.rest().starts_with(*symbol))
+            {
+                Some(symbol) => Some(Ok(self.tokenize_reserved(symbol))),
+                None => Some(Err(self.tokenize_unknown())),
             },
-        };
+        }
     }
 }
 
@@ -236,15 +593,15 @@ fn test_tokens_iterator() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "5",
-            kind: TokenKind::Number(5),
+            text: "5".to_string(),
+            kind: TokenKind::Number(5, NumberType::Int),
             span: 0..1
         }))
     );
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "+",
+            text: "+".to_string(),
             kind: TokenKind::Add,
             span: 1..2
         }))
@@ -252,8 +609,8 @@ fn test_tokens_iterator() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "20",
-            kind: TokenKind::Number(20),
+            text: "20".to_string(),
+            kind: TokenKind::Number(20, NumberType::Int),
             span: 2..4
         }))
     );
@@ -266,15 +623,15 @@ fn test_whitespace() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "3",
-            kind: TokenKind::Number(3),
+            text: "3".to_string(),
+            kind: TokenKind::Number(3, NumberType::Int),
             span: 2..3
         }))
     );
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "-",
+            text: "-".to_string(),
             kind: TokenKind::Sub,
             span: 5..6
         }))
@@ -282,15 +639,15 @@ fn test_whitespace() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "1",
-            kind: TokenKind::Number(1),
+            text: "1".to_string(),
+            kind: TokenKind::Number(1, NumberType::Int),
             span: 6..7
         }))
     );
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "+",
+            text: "+".to_string(),
             kind: TokenKind::Add,
             span: 9..10
         }))
@@ -298,8 +655,8 @@ fn test_whitespace() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "20",
-            kind: TokenKind::Number(20),
+            text: "20".to_string(),
+            kind: TokenKind::Number(20, NumberType::Int),
             span: 10..12
         }))
     );
@@ -313,7 +670,7 @@ fn test_ident() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "a",
+            text: "a".to_string(),
             kind: TokenKind::Ident,
             span: 0..1
         }))
@@ -322,7 +679,7 @@ fn test_ident() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "b",
+            text: "b".to_string(),
             kind: TokenKind::Ident,
             span: 4..5
         }))
@@ -331,7 +688,7 @@ fn test_ident() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "c",
+            text: "c".to_string(),
             kind: TokenKind::Ident,
             span: 8..9
         }))
@@ -345,7 +702,7 @@ fn test_semicolon() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "a",
+            text: "a".to_string(),
             kind: TokenKind::Ident,
             span: 0..1
         }))
@@ -354,7 +711,7 @@ fn test_semicolon() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "b",
+            text: "b".to_string(),
             kind: TokenKind::Ident,
             span: 4..5
         }))
@@ -362,7 +719,7 @@ fn test_semicolon() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: ";",
+            text: ";".to_string(),
             kind: TokenKind::Sep(Separator::SemiColon),
             span: 5..6
         }))
@@ -380,7 +737,7 @@ fn test_local_var() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "foo",
+            text: "foo".to_string(),
             kind: TokenKind::Ident,
             span: 7..10
         }))
@@ -391,7 +748,7 @@ fn test_local_var() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "bar",
+            text: "bar".to_string(),
             kind: TokenKind::Ident,
             span: 19..22
         }))
@@ -409,7 +766,7 @@ fn test_return() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "return",
+            text: "return".to_string(),
             kind: TokenKind::Return,
             span: 6..12
         }))
@@ -417,7 +774,7 @@ fn test_return() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "a",
+            text: "a".to_string(),
             kind: TokenKind::Ident,
             span: 13..14
         }))
@@ -435,7 +792,7 @@ fn test_return_like_ident() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "returnx",
+            text: "returnx".to_string(),
             kind: TokenKind::Ident,
             span: 6..13
         }))
@@ -443,9 +800,381 @@ fn test_return_like_ident() {
     assert_eq!(
         tokens.next(),
         Some(Ok(Token {
-            text: "=",
+            text: "=".to_string(),
             kind: TokenKind::Assign,
             span: 14..15
         }))
     );
 }
+
+#[test]
+fn test_if_else() {
+    let code = "if (a) return 1; else return 2;";
+    let mut tokens = RawStream::new(code);
+    assert_eq!(
+        tokens.next(),
+        Some(Ok(Token {
+            text: "if".to_string(),
+            kind: TokenKind::If,
+            span: 0..2
+        }))
+    );
+    tokens.next(); // (
+    tokens.next(); // a
+    tokens.next(); // )
+    tokens.next(); // return
+    tokens.next(); // 1
+    tokens.next(); // ;
+    assert_eq!(
+        tokens.next(),
+        Some(Ok(Token {
+            text: "else".to_string(),
+            kind: TokenKind::Else,
+            span: 17..21
+        }))
+    );
+}
+
+#[test]
+fn test_loop_keywords() {
+    let code = "while for break continue fortune";
+    let kinds: Vec<TokenKind> = RawStream::new(code)
+        .map(|token| token.unwrap().kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::While,
+            TokenKind::For,
+            TokenKind::Break,
+            TokenKind::Continue,
+            TokenKind::Ident
+        ]
+    );
+}
+
+#[test]
+fn test_curly_bracket() {
+    let code = "{a;}";
+    let kinds: Vec<TokenKind> = RawStream::new(code)
+        .map(|token| token.unwrap().kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Sep(Separator::CurlyBracketL),
+            TokenKind::Ident,
+            TokenKind::Sep(Separator::SemiColon),
+            TokenKind::Sep(Separator::CurlyBracketR),
+        ]
+    );
+}
+
+#[test]
+fn test_int() {
+    let code = "int *p; integer";
+    let kinds: Vec<TokenKind> = RawStream::new(code)
+        .map(|token| token.unwrap().kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Int,
+            TokenKind::Mul,
+            TokenKind::Ident,
+            TokenKind::Sep(Separator::SemiColon),
+            TokenKind::Ident,
+        ]
+    );
+}
+
+#[test]
+fn test_address_of() {
+    let code = "*&x";
+    let kinds: Vec<TokenKind> = RawStream::new(code)
+        .map(|token| token.unwrap().kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![TokenKind::Mul, TokenKind::Amp, TokenKind::Ident]
+    );
+}
+
+#[test]
+fn test_subscript() {
+    let code = "a[3]";
+    let kinds: Vec<TokenKind> = RawStream::new(code)
+        .map(|token| token.unwrap().kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Ident,
+            TokenKind::Sep(Separator::SquareBracketL),
+            TokenKind::Number(3, NumberType::Int),
+            TokenKind::Sep(Separator::SquareBracketR),
+        ]
+    );
+}
+
+#[test]
+fn test_string() {
+    let code = r#"x = "a\"b\n";"#;
+    let mut tokens = RawStream::new(code);
+    tokens.next(); // x
+    tokens.next(); // =
+    assert_eq!(
+        tokens.next(),
+        Some(Ok(Token {
+            text: r#""a\"b\n""#.to_string(),
+            kind: TokenKind::Str,
+            span: 4..12
+        }))
+    );
+    assert_eq!(
+        tokens.next(),
+        Some(Ok(Token {
+            text: ";".to_string(),
+            kind: TokenKind::Sep(Separator::SemiColon),
+            span: 12..13
+        }))
+    );
+}
+
+#[test]
+fn test_unescape() {
+    assert_eq!(unescape(r"a\tb\\"), Some(b"a\tb\\".to_vec()));
+    assert_eq!(unescape(r"\0\101\x41\x7a"), Some(b"\0AAz".to_vec()));
+    assert_eq!(unescape(r"\1234"), Some(b"S4".to_vec()));
+    assert_eq!(unescape(r"\x"), None);
+    assert_eq!(unescape(r"\x100"), None);
+    assert_eq!(unescape(r"\777"), None);
+}
+
+#[test]
+fn test_char_literal() {
+    let kinds: Vec<TokenKind> = RawStream::new(r"'a' '\n' '\0' '\xff'")
+        .map(|token| token.unwrap().kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Number(97, NumberType::Int),
+            TokenKind::Number(10, NumberType::Int),
+            TokenKind::Number(0, NumberType::Int),
+            TokenKind::Number(-1, NumberType::Int),
+        ]
+    );
+}
+
+#[test]
+fn test_unterminated_string() {
+    let code = "x = \"abc;\ny;";
+    let mut tokens = RawStream::new(code);
+    tokens.next(); // x
+    tokens.next(); // =
+    assert_eq!(
+        tokens.next(),
+        Some(Err(CompileError {
+            error_type: CompileErrorType::Tokenizing(TokenizeError::UnterminatedString),
+            pos: Some(4..9),
+        }))
+    );
+}
+
+#[test]
+fn test_comment() {
+    let code = "a // b\n/* c\n * d */ e/**/f";
+    let mut tokens = RawStream::new(code);
+    assert_eq!(
+        tokens.next(),
+        Some(Ok(Token {
+            text: "a".to_string(),
+            kind: TokenKind::Ident,
+            span: 0..1
+        }))
+    );
+    assert_eq!(
+        tokens.next(),
+        Some(Ok(Token {
+            text: "e".to_string(),
+            kind: TokenKind::Ident,
+            span: 20..21
+        }))
+    );
+    assert_eq!(
+        tokens.next(),
+        Some(Ok(Token {
+            text: "f".to_string(),
+            kind: TokenKind::Ident,
+            span: 25..26
+        }))
+    );
+    assert_eq!(tokens.next(), None);
+}
+
+#[test]
+fn test_comment_in_string() {
+    let code = r#""/* a */ // b""#;
+    let mut tokens = RawStream::new(code);
+    assert_eq!(
+        tokens.next(),
+        Some(Ok(Token {
+            text: code.to_string(),
+            kind: TokenKind::Str,
+            span: 0..14
+        }))
+    );
+}
+
+#[test]
+fn test_unterminated_comment() {
+    let code = "a /* b\nc";
+    let mut tokens = RawStream::new(code);
+    tokens.next(); // a
+    assert_eq!(
+        tokens.next(),
+        Some(Err(CompileError {
+            error_type: CompileErrorType::Tokenizing(TokenizeError::UnterminatedComment),
+            pos: Some(2..8),
+        }))
+    );
+    assert_eq!(tokens.next(), None);
+}
+
+#[test]
+fn test_number_literal() {
+    assert_eq!(parse_number("0"), Ok((0, NumberType::Int)));
+    assert_eq!(parse_number("0x1F"), Ok((31, NumberType::Int)));
+    assert_eq!(parse_number("0b101"), Ok((5, NumberType::Int)));
+    assert_eq!(parse_number("017"), Ok((15, NumberType::Int)));
+    assert_eq!(
+        parse_number("2147483648"),
+        Ok((2147483648, NumberType::Long))
+    );
+    assert_eq!(
+        parse_number("0x80000000"),
+        Ok((2147483648, NumberType::UInt))
+    );
+    assert_eq!(
+        parse_number("0xffffffffffffffff"),
+        Ok((-1, NumberType::ULong))
+    );
+    assert_eq!(parse_number("1u"), Ok((1, NumberType::UInt)));
+    assert_eq!(
+        parse_number("4294967296U"),
+        Ok((4294967296, NumberType::ULong))
+    );
+    assert_eq!(parse_number("1L"), Ok((1, NumberType::Long)));
+    assert_eq!(parse_number("1ll"), Ok((1, NumberType::Long)));
+    assert_eq!(parse_number("1uLL"), Ok((1, NumberType::ULong)));
+    assert_eq!(parse_number("1LU"), Ok((1, NumberType::ULong)));
+}
+
+#[test]
+fn test_invalid_number_literal() {
+    assert_eq!(parse_number("08"), Err(TokenizeError::InvalidNumber));
+    assert_eq!(parse_number("0x"), Err(TokenizeError::InvalidNumber));
+    assert_eq!(parse_number("0b2"), Err(TokenizeError::InvalidNumber));
+    assert_eq!(parse_number("1lL"), Err(TokenizeError::InvalidNumber));
+    assert_eq!(parse_number("1uu"), Err(TokenizeError::InvalidNumber));
+    assert_eq!(parse_number("12abc"), Err(TokenizeError::InvalidNumber));
+    assert_eq!(
+        parse_number("9223372036854775808"),
+        Err(TokenizeError::NumberOutOfRange)
+    );
+    assert_eq!(
+        parse_number("99999999999999999999"),
+        Err(TokenizeError::NumberOutOfRange)
+    );
+}
+
+#[test]
+fn test_huge_number() {
+    let code = "99999999999999999999;";
+    let mut tokens = RawStream::new(code);
+    assert_eq!(
+        tokens.next(),
+        Some(Err(CompileError {
+            error_type: CompileErrorType::Tokenizing(TokenizeError::NumberOutOfRange),
+            pos: Some(0..20),
+        }))
+    );
+    assert_eq!(
+        tokens.next().map(|token| token.unwrap().kind),
+        Some(TokenKind::Sep(Separator::SemiColon))
+    );
+}
+
+#[test]
+fn test_logical_and_bitwise_operators() {
+    let code = "!a && ~b || c & d | e ^ f << 1 >> 2 % 3";
+    let tokens = RawStream::new(code);
+    let kinds: Vec<TokenKind> = tokens.map(|token| token.unwrap().kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Not,
+            TokenKind::Ident,
+            TokenKind::AndAnd,
+            TokenKind::Tilde,
+            TokenKind::Ident,
+            TokenKind::OrOr,
+            TokenKind::Ident,
+            TokenKind::Amp,
+            TokenKind::Ident,
+            TokenKind::Pipe,
+            TokenKind::Ident,
+            TokenKind::Caret,
+            TokenKind::Ident,
+            TokenKind::Shl,
+            TokenKind::Number(1, NumberType::Int),
+            TokenKind::Shr,
+            TokenKind::Number(2, NumberType::Int),
+            TokenKind::Mod,
+            TokenKind::Number(3, NumberType::Int),
+        ]
+    );
+}
+
+#[test]
+fn test_assignment_operators() {
+    let code = "a+=b-=c<<=d>>=e|=f^=g&=h*=i/=j%=k++--?:";
+    let tokens = RawStream::new(code);
+    let kinds: Vec<TokenKind> = tokens
+        .map(|token| token.unwrap().kind)
+        .filter(|kind| *kind != TokenKind::Ident)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::AddAssign,
+            TokenKind::SubAssign,
+            TokenKind::ShlAssign,
+            TokenKind::ShrAssign,
+            TokenKind::OrAssign,
+            TokenKind::XorAssign,
+            TokenKind::AndAssign,
+            TokenKind::MulAssign,
+            TokenKind::DivAssign,
+            TokenKind::ModAssign,
+            TokenKind::Inc,
+            TokenKind::Dec,
+            TokenKind::Question,
+            TokenKind::Colon,
+        ]
+    );
+}
+
+#[test]
+fn test_take_line() {
+    let code = "a /* b\n c */ d \"/*\" '\"' // e /* f\ng\\\nh\ni";
+    let mut stream = RawStream::new(code);
+    assert_eq!(
+        stream.take_line(),
+        ("a /* b\n c */ d \"/*\" '\"' // e /* f", 0..33)
+    );
+    assert_eq!(stream.next().unwrap().unwrap().text, "g");
+    assert_eq!(stream.take_line().0, "\\\nh");
+    assert_eq!(stream.next().unwrap().unwrap().text, "i");
+}
@@ -0,0 +1,291 @@
+use std::fmt;
+
+use crate::errors::{CompileError, CompileErrorType, TypeError};
+use crate::parser::{Node, NodeKind};
+use crate::tokenizer::NumberType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Char,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    Ptr(Box<Type>),          // pointer to the type
+    Array(Box<Type>, usize), // array of the type with its length
+}
+
+impl Type {
+    pub fn pointer_to(base: Type) -> Type {
+        Type::Ptr(Box::new(base))
+    }
+
+    pub fn array_of(base: Type, len: usize) -> Type {
+        Type::Array(Box::new(base), len)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Type::Char | Type::Int | Type::UInt | Type::Long | Type::ULong
+        )
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Type::UInt | Type::ULong)
+    }
+
+    // Usual arithmetic conversions: integers smaller than int are promoted,
+    // then the larger type wins, and unsigned wins between the same size
+    pub fn common(lhs: &Type, rhs: &Type) -> Type {
+        let promote = |ty: &Type| match ty {
+            Type::Char => Type::Int,
+            ty => ty.clone(),
+        };
+        let (lhs, rhs) = (promote(lhs), promote(rhs));
+        if lhs.size() != rhs.size() {
+            return if lhs.size() > rhs.size() { lhs } else { rhs };
+        }
+        if rhs.is_unsigned() {
+            rhs
+        } else {
+            lhs
+        }
+    }
+
+    // the pointed type, or the element type of an array
+    pub fn base(&self) -> Option<&Type> {
+        match self {
+            Type::Ptr(base) | Type::Array(base, _) => Some(base),
+            _ => None,
+        }
+    }
+
+    // an array is converted to a pointer to its first element
+    pub fn decay(self) -> Type {
+        match self {
+            Type::Array(base, _) => Type::Ptr(base),
+            ty => ty,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Char => 1,
+            Type::Int | Type::UInt => 4,
+            Type::Long | Type::ULong | Type::Ptr(_) => 8,
+            Type::Array(base, len) => base.size() * len,
+        }
+    }
+
+    pub fn align(&self) -> usize {
+        match self {
+            Type::Char => 1,
+            Type::Int | Type::UInt => 4,
+            Type::Long | Type::ULong | Type::Ptr(_) => 8,
+            Type::Array(base, _) => base.align(),
+        }
+    }
+}
+
+// C spelling of the type, like "int*" and "char[2][3]"
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Char => write!(f, "char"),
+            Type::Int => write!(f, "int"),
+            Type::UInt => write!(f, "unsigned int"),
+            Type::Long => write!(f, "long"),
+            Type::ULong => write!(f, "unsigned long"),
+            Type::Ptr(base) => write!(f, "{}*", base),
+            Type::Array(..) => {
+                let mut ty = self;
+                let mut lens = vec![];
+                while let Type::Array(base, len) = ty {
+                    lens.push(len);
+                    ty = base;
+                }
+                write!(f, "{}", ty)?;
+                lens.iter().try_for_each(|len| write!(f, "[{}]", len))
+            }
+        }
+    }
+}
+
+impl From<NumberType> for Type {
+    fn from(ty: NumberType) -> Type {
+        match ty {
+            NumberType::Int => Type::Int,
+            NumberType::UInt => Type::UInt,
+            NumberType::Long => Type::Long,
+            NumberType::ULong => Type::ULong,
+        }
+    }
+}
+
+// How the types of a binary operator and its operands decide its result,
+// which both `Codegen` and `Interpreter` follow
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub lhs_scale: Option<usize>, // pointer arithmetic is scaled by the size of the pointed type
+    pub rhs_scale: Option<usize>,
+    pub operand_ty: Option<Type>, // integer operands are converted to their common type
+    pub unsigned: bool,           // division, remainder, comparison and right shift are unsigned
+    pub result_ty: Option<Type>,  // results narrower than 64 bits wrap around
+}
+
+impl Operation {
+    pub fn new(node: &Node, lhs_ty: Option<&Type>, rhs_ty: Option<&Type>) -> Operation {
+        let shift = matches!(node.kind, NodeKind::Shl | NodeKind::Shr);
+        // shifts keep the promoted type of the left operand, which is the type of the result
+        let operand_ty = match (lhs_ty, rhs_ty) {
+            _ if shift => None,
+            (Some(l), Some(r)) if l.is_integer() && r.is_integer() => Some(Type::common(l, r)),
+            _ => None,
+        };
+        let unsigned = if shift {
+            node.ty.as_ref().is_some_and(Type::is_unsigned)
+        } else {
+            operand_ty.as_ref().is_some_and(Type::is_unsigned)
+        };
+        Operation {
+            lhs_scale: lhs_ty.and_then(Type::base).map(Type::size),
+            rhs_scale: rhs_ty.and_then(Type::base).map(Type::size),
+            operand_ty,
+            unsigned,
+            result_ty: node.ty.clone(),
+        }
+    }
+}
+
+fn type_error(error: TypeError, node: &Node) -> CompileError {
+    CompileError {
+        error_type: CompileErrorType::Typing(error),
+        pos: Some(node.span.clone()),
+    }
+}
+
+// Type of an arithmetic operation on two integers
+fn common_type(lhs: Option<Type>, rhs: Option<Type>) -> Option<Type> {
+    Some(Type::common(&lhs?, &rhs?))
+}
+
+// Set `ty` of the node and its children, and check the operands of operators
+pub fn add_type(node: &mut Node) -> Result<(), CompileError> {
+    for child in [
+        &mut node.lhs,
+        &mut node.rhs,
+        &mut node.cond,
+        &mut node.then,
+        &mut node.els,
+        &mut node.init,
+        &mut node.inc,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        add_type(child)?;
+    }
+    for child in node.body.iter_mut().chain(node.args.iter_mut()) {
+        add_type(child)?;
+    }
+
+    let lhs_ty = node.lhs.as_ref().and_then(|lhs| lhs.ty.clone());
+    let rhs_ty = node.rhs.as_ref().and_then(|rhs| rhs.ty.clone());
+    node.ty = match node.kind {
+        // literals are typed by the parser, and the others are int
+        NodeKind::Number(_) => node.ty.take().or(Some(Type::Int)),
+        NodeKind::LogAnd
+        | NodeKind::LogOr
+        | NodeKind::Not
+        | NodeKind::Eq
+        | NodeKind::NotEq
+        | NodeKind::Less
+        | NodeKind::LessEq => Some(Type::Int),
+        // calls are typed by the parser, from the declared return type
+        NodeKind::FuncCall => node.ty.take().or(Some(Type::Int)),
+        // variables are typed by the parser
+        NodeKind::Var(_) | NodeKind::GlobalVar => node.ty.take(),
+        // arrays are added and subtracted as pointers
+        NodeKind::Add => match (lhs_ty.map(Type::decay), rhs_ty.map(Type::decay)) {
+            (Some(Type::Ptr(_)), Some(Type::Ptr(_))) => {
+                return Err(type_error(TypeError::InvalidOperand, node))
+            }
+            (Some(ty @ Type::Ptr(_)), _) | (_, Some(ty @ Type::Ptr(_))) => Some(ty),
+            (lhs, rhs) => common_type(lhs, rhs),
+        },
+        NodeKind::Sub => match (lhs_ty.map(Type::decay), rhs_ty.map(Type::decay)) {
+            (Some(Type::Ptr(_)), Some(Type::Ptr(_))) => Some(Type::Long),
+            (Some(ty @ Type::Ptr(_)), _) => Some(ty),
+            (_, Some(Type::Ptr(_))) => return Err(type_error(TypeError::InvalidOperand, node)),
+            (lhs, rhs) => common_type(lhs, rhs),
+        },
+        NodeKind::Mul
+        | NodeKind::Div
+        | NodeKind::Mod
+        | NodeKind::BitAnd
+        | NodeKind::BitOr
+        | NodeKind::BitXor => {
+            if !lhs_ty.as_ref().is_some_and(Type::is_integer)
+                || !rhs_ty.as_ref().is_some_and(Type::is_integer)
+            {
+                return Err(type_error(TypeError::InvalidOperand, node));
+            }
+            common_type(lhs_ty, rhs_ty)
+        }
+        // the result has the promoted type of the left operand
+        NodeKind::Shl | NodeKind::Shr => {
+            if !lhs_ty.as_ref().is_some_and(Type::is_integer)
+                || !rhs_ty.as_ref().is_some_and(Type::is_integer)
+            {
+                return Err(type_error(TypeError::InvalidOperand, node));
+            }
+            common_type(lhs_ty, Some(Type::Int))
+        }
+        NodeKind::BitNot => {
+            if !lhs_ty.as_ref().is_some_and(Type::is_integer) {
+                return Err(type_error(TypeError::InvalidOperand, node));
+            }
+            common_type(lhs_ty, Some(Type::Int))
+        }
+        NodeKind::Assign => {
+            if let Some(Type::Array(..)) = lhs_ty {
+                return Err(type_error(TypeError::NotLValue, node));
+            }
+            lhs_ty
+        }
+        // the value is stored back to the target, the lhs of the operation
+        NodeKind::AssignOp | NodeKind::PostAssignOp => {
+            let operation = node.lhs.as_deref().expect("assignment without operation");
+            let target_ty = operation.lhs.as_ref().and_then(|target| target.ty.clone());
+            if let Some(Type::Array(..)) = target_ty {
+                return Err(type_error(TypeError::NotLValue, node));
+            }
+            target_ty
+        }
+        NodeKind::Cond => {
+            let then_ty = node.then.as_ref().and_then(|then| then.ty.clone());
+            let els_ty = node.els.as_ref().and_then(|els| els.ty.clone());
+            match (then_ty.map(Type::decay), els_ty.map(Type::decay)) {
+                (Some(ty @ Type::Ptr(_)), _) | (_, Some(ty @ Type::Ptr(_))) => Some(ty),
+                (then, els) => common_type(then, els),
+            }
+        }
+        NodeKind::Comma => rhs_ty,
+        NodeKind::Addr => lhs_ty.map(Type::pointer_to),
+        NodeKind::Deref => match lhs_ty.as_ref().and_then(Type::base) {
+            Some(base) => Some(base.clone()),
+            None => return Err(type_error(TypeError::NotPointer, node)),
+        },
+        // sizeof is evaluated at compile time
+        NodeKind::Sizeof => {
+            let size = lhs_ty.map_or(0, |ty| ty.size());
+            node.kind = NodeKind::Number(size as i64);
+            node.lhs = None;
+            Some(Type::ULong)
+        }
+        // statements have no type
+        _ => None,
+    };
+    Ok(())
+}
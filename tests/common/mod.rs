@@ -0,0 +1,93 @@
+// shared by the test crates, each of which uses only a part of it
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use r9cc::codegen::Codegen;
+
+pub const TESTCASES: &str = "tests/testcases";
+
+// Names of tests/testcases/<name>, sorted
+pub fn testcases() -> io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(TESTCASES)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<_>>()?;
+    names.sort();
+    Ok(names)
+}
+
+// The program `in` and the expected exit code `out` of the testcase
+pub fn read_testcase(name: &str) -> io::Result<(String, String)> {
+    let dir = Path::new(TESTCASES).join(name);
+    let src = fs::read_to_string(dir.join("in"))?;
+    let expected = fs::read_to_string(dir.join("out"))?;
+    Ok((src, expected.trim_end().to_string()))
+}
+
+// The compiled programs are assembled and linked with the system `cc`
+pub fn cc_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        Command::new("cc")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    })
+}
+
+// A directory of its own for each testcase, removed however the test ends
+struct WorkDir(PathBuf);
+
+impl WorkDir {
+    fn new(name: &str) -> io::Result<Self> {
+        let dir = env::temp_dir().join(format!("r9cc-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir)?;
+        Ok(WorkDir(dir))
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Compile `src` with cc and run it. Returns the exit code, or the error message.
+pub fn run_compiled(name: &str, src: &str) -> Result<String, String> {
+    let assembly = match Codegen::compile(src) {
+        Ok(assembly) => assembly.join("\n") + "\n",
+        Err(errors) => {
+            let errors: Vec<String> = errors.iter().map(|e| e.render("in", src)).collect();
+            return Err(format!("cannot compile\n{}", errors.join("\n")));
+        }
+    };
+    let work = WorkDir::new(name).map_err(|err| err.to_string())?;
+    let (asm, exe) = (work.0.join("tmp.s"), work.0.join("tmp"));
+    fs::write(&asm, &assembly).map_err(|err| err.to_string())?;
+    let cc = Command::new("cc")
+        .arg("-o")
+        .arg(&exe)
+        .arg(&asm)
+        .output()
+        .map_err(|err| err.to_string())?;
+    if !cc.status.success() {
+        return Err(format!(
+            "cannot assemble\n{}\n\n{}",
+            String::from_utf8_lossy(&cc.stderr),
+            assembly
+        ));
+    }
+    let status = Command::new(&exe)
+        .output()
+        .map_err(|err| err.to_string())?
+        .status;
+    // a program killed by a signal has no exit code
+    Ok(status
+        .code()
+        .map_or_else(|| status.to_string(), |code| code.to_string()))
+}
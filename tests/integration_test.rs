@@ -1,73 +1,36 @@
-use std::fs::{self, File};
-use std::io::prelude::*;
-use std::path::{Path, PathBuf};
+use libtest_mimic::{Arguments, Trial};
 
-// read ./testcases/**/{in, out, (asm)}
-// TODO: parallelly
+use r9cc::codegen::Codegen;
 
-fn remove_trailing_newline(mut s: String) -> String {
-    if s.ends_with('\n') {
-        s.pop();
-    }
-    s
-}
-
-#[test]
-fn make_generated_test_sh() -> std::io::Result<()> {
-    let template_prefix = r###"#!/bin/bash
-assert() {
-  expected="$1"
-  input="$2"
+mod common;
 
-  # use release binary
-  ./target/release/r9cc "$input" > tmp.s
-  cc -o tmp tmp.s
-  ./tmp
-  actual="$?"
-
-  if [ "$actual" = "$expected" ]; then
-    echo "$input => $actual"
-  else
-    echo "$input => $expected expected, but got $actual"
-    exit 1
-  fi
-}
+use common::{cc_available, read_testcase, run_compiled, testcases};
 
-# build release binary
-cargo build --release
-
-# --- This is generated test ---
-
-"###;
-    let template_postfix = "\n\n# --- end of testcases ---\n\necho OK\n";
-    let mut testcases = vec![];
-    let dirs: Vec<PathBuf> = fs::read_dir(Path::new("tests/testcases"))?
-        .map(|entry| {
-            let entry = entry.unwrap();
-            entry.path()
+// Each tests/testcases/<name>/{in, out} is compiled, linked with cc and run,
+// and its exit code is compared with `out`. The testcases are found at runtime,
+// and each of them is a test of its own, ignored when cc is not available.
+fn main() {
+    let args = Arguments::from_args();
+    let trials = testcases()
+        .expect("cannot read tests/testcases")
+        .into_iter()
+        .map(|name| {
+            Trial::test(name.clone(), move || Ok(run_testcase(&name)?))
+                .with_ignored_flag(!cc_available())
         })
         .collect();
-    for dir in dirs {
-        let test_in = dir.join("in");
-        let test_out = dir.join("out");
-        // let test_asm = dir.join("asm");
-        let testcase = format!(
-            "assert '{}' '{}'   # {}",
-            remove_trailing_newline(fs::read_to_string(test_out)?),
-            remove_trailing_newline(fs::read_to_string(test_in)?),
-            dir.as_path().file_name().unwrap().to_str().unwrap()
-        );
-        testcases.push(testcase);
+    libtest_mimic::run(&args, trials).exit();
+}
+
+fn run_testcase(name: &str) -> Result<(), String> {
+    let (src, expected) = read_testcase(name).map_err(|err| err.to_string())?;
+    let actual = run_compiled(name, &src)?;
+    if actual != expected {
+        let assembly = Codegen::compile(&src).map_or_else(|_| String::new(), |a| a.join("\n"));
+        return Err(format!(
+            "wrong exit code\n--- expected\n+++ actual\n-{}\n+{}\n\n{}\n\n{}",
+            expected, actual, src, assembly
+        ));
     }
-    let mut file = File::create("generated_test.sh")?;
-    file.write_all(
-        format!(
-            "{}{}{}",
-            template_prefix,
-            testcases.join("\n"),
-            template_postfix
-        )
-        .as_bytes(),
-    )?;
     Ok(())
 }
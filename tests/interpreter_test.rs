@@ -0,0 +1,36 @@
+use r9cc::interpreter::Interpreter;
+
+mod common;
+
+use common::{read_testcase, testcases};
+
+// Testcases which only compiled programs can run, with the reason
+const SKIPPED: &[(&str, &str)] = &[
+    ("func_5", "calls abs() of the C library"),
+    ("func_8", "calls abs() of the C library"),
+    ("string_5", "calls printf() of the C library"),
+];
+
+// The interpreter must give the exit code in `out` of every testcase,
+// like the compiled programs run by integration_test
+#[test]
+fn interpret_testcases() -> std::io::Result<()> {
+    let mut failures = vec![];
+    for name in testcases()? {
+        if let Some((_, reason)) = SKIPPED.iter().find(|(skipped, _)| *skipped == name) {
+            println!("skipped {}: {}", name, reason);
+            continue;
+        }
+        let (src, expected) = read_testcase(&name)?;
+        // the exit status is the low 8 bits of the value
+        match Interpreter::run(&src) {
+            Ok(value) if (value & 0xff).to_string() == expected => {}
+            result => failures.push(format!(
+                "{}: expected {}, interpreted {:?}",
+                name, expected, result
+            )),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    Ok(())
+}